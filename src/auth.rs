@@ -0,0 +1,320 @@
+use super::MangadexError;
+use serde::Deserialize;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tracing::debug;
+
+pub const DEFAULT_TOKEN_URL: &str =
+    "https://auth.mangadex.org/realms/mangadex/protocol/openid-connect/token";
+
+/// Tokens are renewed this long before they expire, so a request is never sent
+/// with a token about to be rejected.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// Login of a MangaDex personal API client.
+///
+/// Access tokens are obtained with the password grant and renewed with the
+/// refresh token once expired, falling back to the password when the refresh
+/// token is rejected. Clones share the same tokens.
+#[derive(Clone)]
+pub struct MangadexAuth {
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+    username: Option<String>,
+    password: Option<String>,
+    session: Arc<Mutex<Session>>,
+}
+
+#[derive(Debug, Default)]
+struct Session {
+    access_token: Option<String>,
+    expires_at: Option<Instant>,
+    refresh_token: Option<String>,
+    refresh_expires_at: Option<Instant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+    refresh_token: Option<String>,
+    refresh_expires_in: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenError {
+    error: String,
+    error_description: Option<String>,
+}
+
+impl Debug for MangadexAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MangadexAuth")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Session {
+    fn access_token(&self, now: Instant) -> Option<&str> {
+        let expires_at = self.expires_at?;
+        (now + EXPIRY_MARGIN < expires_at)
+            .then_some(self.access_token.as_deref())
+            .flatten()
+    }
+
+    fn refresh_token(&self, now: Instant) -> Option<&str> {
+        match self.refresh_expires_at {
+            Some(expires_at) if now + EXPIRY_MARGIN >= expires_at => None,
+            _ => self.refresh_token.as_deref(),
+        }
+    }
+
+    fn update(&mut self, response: TokenResponse, now: Instant) {
+        self.access_token = Some(response.access_token);
+        self.expires_at = Some(now + Duration::from_secs(response.expires_in));
+        // The refresh token may be rotated, the previous one is kept otherwise
+        if let Some(refresh_token) = response.refresh_token {
+            self.refresh_token = Some(refresh_token);
+            self.refresh_expires_at = response
+                .refresh_expires_in
+                .filter(|&x| x > 0)
+                .map(|x| now + Duration::from_secs(x));
+        }
+    }
+}
+
+impl MangadexAuth {
+    /// Credentials of a personal client, from the API clients page of the
+    /// MangaDex settings.
+    pub fn new(client_id: impl ToString, client_secret: impl ToString) -> Self {
+        Self {
+            token_url: DEFAULT_TOKEN_URL.to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            username: None,
+            password: None,
+            session: Arc::default(),
+        }
+    }
+
+    /// Account the client belongs to.
+    pub fn password(mut self, username: impl ToString, password: impl ToString) -> Self {
+        self.username = Some(username.to_string());
+        self.password = Some(password.to_string());
+        self
+    }
+
+    /// Start from a refresh token saved by an earlier login.
+    pub fn refresh_token(self, refresh_token: impl ToString) -> Self {
+        let session = Session {
+            refresh_token: Some(refresh_token.to_string()),
+            ..Session::default()
+        };
+        Self {
+            session: Arc::new(Mutex::new(session)),
+            ..self
+        }
+    }
+
+    pub fn token_url(mut self, token_url: impl ToString) -> Self {
+        self.token_url = token_url.to_string();
+        self
+    }
+
+    pub(crate) fn token_endpoint(&self) -> &str {
+        &self.token_url
+    }
+
+    /// Current refresh token, to be saved for the next login.
+    pub async fn current_refresh_token(&self) -> Option<String> {
+        self.session.lock().await.refresh_token.clone()
+    }
+
+    /// A valid access token, logging in or refreshing when needed.
+    pub async fn access_token(&self, http: &reqwest::Client) -> Result<String, MangadexError> {
+        // The lock is held during the request so concurrent callers wait for a
+        // single refresh instead of each using the refresh token
+        let mut session = self.session.lock().await;
+        let now = Instant::now();
+        if let Some(access_token) = session.access_token(now) {
+            return Ok(access_token.to_string());
+        }
+
+        if let Some(refresh_token) = session.refresh_token(now).map(str::to_string) {
+            let form = [
+                ("grant_type", "refresh_token"),
+                ("refresh_token", &refresh_token),
+                ("client_id", &self.client_id),
+                ("client_secret", &self.client_secret),
+            ];
+            match self.request(http, &form).await {
+                Ok(response) => {
+                    session.update(response, Instant::now());
+                    return Ok(session.access_token.clone().unwrap_or_default());
+                }
+                Err(MangadexError::AuthError(e)) if self.password.is_some() => {
+                    debug!("Refresh token rejected, logging in again: {e}");
+                    *session = Session::default();
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let (Some(username), Some(password)) = (&self.username, &self.password) else {
+            return Err(MangadexError::AuthError(String::from(
+                "session expired and no password to log in again",
+            )));
+        };
+        let form = [
+            ("grant_type", "password"),
+            ("username", username),
+            ("password", password),
+            ("client_id", &self.client_id),
+            ("client_secret", &self.client_secret),
+        ];
+        let response = self.request(http, &form).await?;
+        session.update(response, Instant::now());
+        Ok(session.access_token.clone().unwrap_or_default())
+    }
+
+    async fn request(
+        &self,
+        http: &reqwest::Client,
+        form: &[(&str, &str)],
+    ) -> Result<TokenResponse, MangadexError> {
+        let response = http.post(&self.token_url).form(form).send().await?;
+        let status = response.status();
+        // Rejected credentials are told apart from server failures, which
+        // should not make the session be dropped
+        if status.is_client_error() {
+            let bytes = response.bytes().await?;
+            let message = match serde_json::from_slice::<TokenError>(&bytes) {
+                Ok(e) => e.error_description.unwrap_or(e.error),
+                Err(_) => status.to_string(),
+            };
+            return Err(MangadexError::AuthError(message));
+        }
+        let bytes = response.error_for_status()?.bytes().await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use serde_json::json;
+
+    fn token_response(access_token: &str, refresh_token: &str, expires_in: u64) -> MockResponse {
+        MockResponse::json(json!({
+            "access_token": access_token,
+            "expires_in": expires_in,
+            "refresh_token": refresh_token,
+            "refresh_expires_in": 2400,
+            "token_type": "Bearer",
+        }))
+    }
+
+    fn grants(server: &MockServer) -> Vec<String> {
+        server
+            .requests()
+            .iter()
+            .filter(|r| r.path() == "/token")
+            .flat_map(|r| r.form_values("grant_type"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_password_grant() {
+        let server = MockServer::start().await;
+        server.mount("/token", token_response("access", "refresh", 900));
+        let auth = MangadexAuth::new("personal-client-abc", "secret")
+            .password("reader", "hunter2")
+            .token_url(format!("{}/token", server.url()));
+        let http = reqwest::Client::new();
+
+        assert_eq!(auth.access_token(&http).await.unwrap(), "access");
+        assert_eq!(auth.clone().access_token(&http).await.unwrap(), "access");
+        assert_eq!(grants(&server), ["password"]);
+
+        let request = &server.requests()[0];
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.form_values("username"), ["reader"]);
+        assert_eq!(request.form_values("password"), ["hunter2"]);
+        assert_eq!(request.form_values("client_id"), ["personal-client-abc"]);
+        assert_eq!(request.form_values("client_secret"), ["secret"]);
+        assert_eq!(
+            auth.current_refresh_token().await.as_deref(),
+            Some("refresh")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rotation() {
+        let server = MockServer::start().await;
+        server.mount_with("/token", |request| {
+            match request.form_values("grant_type")[0].as_str() {
+                "password" => token_response("access-1", "refresh-1", 0),
+                _ => match request.form_values("refresh_token")[0].as_str() {
+                    "refresh-1" => token_response("access-2", "refresh-2", 0),
+                    "refresh-2" => token_response("access-3", "refresh-3", 900),
+                    _ => MockResponse::new(400),
+                },
+            }
+        });
+        let auth = MangadexAuth::new("id", "secret")
+            .password("reader", "hunter2")
+            .token_url(format!("{}/token", server.url()));
+        let http = reqwest::Client::new();
+
+        // Tokens expiring right away are renewed on every call
+        assert_eq!(auth.access_token(&http).await.unwrap(), "access-1");
+        assert_eq!(auth.access_token(&http).await.unwrap(), "access-2");
+        assert_eq!(auth.access_token(&http).await.unwrap(), "access-3");
+        assert_eq!(auth.access_token(&http).await.unwrap(), "access-3");
+        assert_eq!(
+            grants(&server),
+            ["password", "refresh_token", "refresh_token"]
+        );
+        assert_eq!(
+            auth.current_refresh_token().await.as_deref(),
+            Some("refresh-3")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_refresh_token() {
+        let server = MockServer::start().await;
+        server.mount_with("/token", |request| {
+            match request.form_values("grant_type")[0].as_str() {
+                "password" => token_response("access", "refresh", 900),
+                _ => MockResponse::new(400).body(
+                    json!({"error": "invalid_grant", "error_description": "Token is not active"})
+                        .to_string(),
+                ),
+            }
+        });
+        let http = reqwest::Client::new();
+        let token_url = format!("{}/token", server.url());
+
+        let auth = MangadexAuth::new("id", "secret")
+            .refresh_token("stale")
+            .token_url(&token_url);
+        let e = auth.access_token(&http).await.unwrap_err();
+        assert!(matches!(e, MangadexError::AuthError(ref x) if x == "Token is not active"));
+
+        let auth = auth.password("reader", "hunter2");
+        assert_eq!(auth.access_token(&http).await.unwrap(), "access");
+        assert_eq!(
+            grants(&server),
+            ["refresh_token", "refresh_token", "password"]
+        );
+    }
+}
@@ -1,8 +1,14 @@
 use std::path::PathBuf;
 
 use anyhow::Ok;
-use clap::{Parser, ArgAction};
-use mangadex::{ChapterDownloadRequest, ChapterDownloader};
+use clap::{ArgAction, Parser};
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use mangadex::{
+    ChapterDownloadRequest, ChapterDownloader, MangadexClient, ProgressEvent, ProgressListener,
+    RetryPolicy,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
 use tower::Service;
 
 #[derive(Debug, Parser)]
@@ -25,6 +31,15 @@ struct Arguments {
         help = "download uncompressed images"
     )]
     data_saver: bool,
+    #[arg(long, default_value_t = 3, help = "number of retries for each page")]
+    retries: u32,
+    #[arg(
+        long,
+        help = "do not report image fetches to the MangaDex@Home network"
+    )]
+    no_report: bool,
+    #[arg(long, help = "keep the chapter even if some pages failed to download")]
+    allow_incomplete: bool,
 }
 
 #[tokio::main]
@@ -35,9 +50,62 @@ async fn main() -> anyhow::Result<()> {
     } else {
         ChapterDownloadRequest::new(&args.chapter)
     };
-    let req = req.path(&args.path).data_saver(args.data_saver);
+    let req = req
+        .path(&args.path)
+        .data_saver(args.data_saver)
+        .retry(RetryPolicy::new().max_retries(args.retries))
+        .allow_incomplete(args.allow_incomplete);
 
-    let mut download_service = ChapterDownloader;
-    download_service.call(req).await?;
+    let mut client = MangadexClient::builder();
+    if args.no_report {
+        client = client.disable_reporting();
+    }
+    let bar = ProgressBar::new(0).with_style(
+        ProgressStyle::with_template("[{bar:40}] {pos}/{len} pages {msg} eta {eta}")?
+            .progress_chars("=> "),
+    );
+    let mut download_service =
+        ChapterDownloader::new(client.build()?).progress(progress_listener(bar.clone()));
+    let result = download_service.call(req).await;
+    bar.finish();
+    let report = result?;
+    println!(
+        "Downloaded {} pages ({}) to {} in {:.1?}",
+        report.fetched().count(),
+        HumanBytes(report.fetched().map(|p| *p.size()).sum()),
+        report.path().display(),
+        report.elapsed()
+    );
+    for failure in report.failed() {
+        println!("Missing page {}: {}", failure.index(), failure.error());
+    }
     Ok(())
 }
+
+fn progress_listener(bar: ProgressBar) -> impl ProgressListener {
+    let start = Instant::now();
+    let received = AtomicU64::new(0);
+    move |event| match event {
+        ProgressEvent::ChapterStarted {
+            total_pages,
+            present_pages,
+            ..
+        } => {
+            bar.set_length(total_pages as u64);
+            bar.set_position(present_pages as u64);
+        }
+        ProgressEvent::PageDone { done, bytes, .. } => {
+            let received = received.fetch_add(bytes as u64, Ordering::Relaxed) + bytes as u64;
+            let rate = received as f64 / start.elapsed().as_secs_f64().max(0.001);
+            bar.set_position(done as u64);
+            bar.set_message(format!("{}/s", HumanBytes(rate as u64)));
+        }
+        ProgressEvent::Retry {
+            page,
+            attempt,
+            error,
+            ..
+        } => bar.println(format!("retry page {page} (attempt {attempt}): {error}")),
+        ProgressEvent::ChapterFinished { .. } => {}
+    }
+}
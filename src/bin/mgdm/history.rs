@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use mangadex::{History, HistoryEntry, HISTORY_FILE_NAME};
+
+const TITLE_WIDTH: usize = 30;
+
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+    #[arg(long, help = "only chapters of this manga id")]
+    manga: Option<String>,
+    #[arg(short = 'n', long, help = "only the last chapters downloaded")]
+    last: Option<usize>,
+    #[arg(long, help = "only chapters no longer on disk")]
+    missing: bool,
+    #[arg(short, long, default_value = ".", help = "destination folder")]
+    path: PathBuf,
+    #[arg(
+        long,
+        help = "journal of the downloaded chapters, .mgdm-history.jsonl in the destination folder by default"
+    )]
+    history: Option<PathBuf>,
+}
+
+pub fn run(args: &HistoryArgs) -> anyhow::Result<()> {
+    let path = args
+        .history
+        .clone()
+        .unwrap_or_else(|| args.path.join(HISTORY_FILE_NAME));
+    let history = History::open(&path)?;
+
+    let mut entries: Vec<&HistoryEntry> = history
+        .entries()
+        .iter()
+        .filter(|e| args.manga.is_none() || args.manga.as_ref() == Some(&e.manga))
+        .filter(|e| !args.missing || !e.is_present())
+        .collect();
+    entries.sort_by(|a, b| a.downloaded_at.cmp(&b.downloaded_at));
+    if let Some(last) = args.last {
+        entries.drain(..entries.len().saturating_sub(last));
+    }
+    if entries.is_empty() {
+        println!("No chapters in {}", path.display());
+        return Ok(());
+    }
+
+    println!(
+        "{:<20}  {:<TITLE_WIDTH$}  {:>6}  {:>4}  {:<5}  {:>5}  {:<7}  location",
+        "downloaded", "title", "ch", "vol", "lang", "pages", "status"
+    );
+    for entry in entries {
+        let mut title = entry.title.clone();
+        if title.chars().count() > TITLE_WIDTH {
+            title = title.chars().take(TITLE_WIDTH - 1).collect::<String>() + "…";
+        }
+        let status = if !entry.is_complete() && (entry.is_present() || entry.path.exists()) {
+            "partial"
+        } else if entry.is_present() {
+            "ok"
+        } else {
+            "missing"
+        };
+        let location = entry.archive.as_ref().unwrap_or(&entry.path);
+        println!(
+            "{:<20}  {:<TITLE_WIDTH$}  {:>6}  {:>4}  {:<5}  {:>5}  {:<7}  {}",
+            entry.downloaded_at,
+            title,
+            entry.chapter.as_deref().unwrap_or("-"),
+            entry.volume.as_deref().unwrap_or("-"),
+            entry.language,
+            entry.pages,
+            status,
+            location.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,764 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+use std::time::Instant;
+use std::{path::PathBuf, time::Duration};
+
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use futures::StreamExt;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use mangadex::{
+    ChapterDownloadRequest, ChapterDownloader, ChapterInfo, ChapterManifest, ComicInfo, Cover,
+    CoverQuery, CoverSize, GetChapters, History, HistoryEntry, MangaFeed, MangaInfo, MangaQuery,
+    MangadexClient, PackageChapter, PackageFormat, PackageMode, Packager, Preference,
+    ProgressEvent, ProgressListener, RetryPolicy, Selection, SelectionPolicy, Volume,
+    DEFAULT_CONCURRENCY, HISTORY_FILE_NAME,
+};
+use std::path::Path;
+use tower::{ServiceBuilder, ServiceExt};
+
+mod history;
+mod search;
+mod sync;
+mod update;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "mdgm",
+    version,
+    author,
+    about = "CLI tool to download manga from mangadex",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Arguments {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(required = true, help = "manga id or url")]
+    manga: Option<String>,
+    #[command(flatten)]
+    download: DownloadOptions,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Search manga by title and filters, then pick one to download
+    Search(search::SearchArgs),
+    /// Download the chapters of followed manga published since the last sync
+    Sync(sync::SyncArgs),
+    /// Download the new chapters of every manga listed in a library file
+    Update(update::UpdateArgs),
+    /// List the chapters downloaded so far
+    History(history::HistoryArgs),
+}
+
+#[derive(Debug, Clone, Args)]
+struct DownloadOptions {
+    #[arg(short, long, default_value_t= String::from("en"), help="translation language" )]
+    language: String,
+    #[arg(short, long, help = "translation group")]
+    groups: Vec<String>,
+    #[arg(short, long, group = "range")]
+    chapters: Vec<f32>,
+    #[arg(short, long, group = "range")]
+    volumes: Vec<f32>,
+    #[command(flatten)]
+    chapter_range: ChapterRange,
+    #[command(flatten)]
+    volume_range: VolumeRange,
+    #[arg(
+        long,
+        conflicts_with_all = ["range", "chapter_range", "volume_range"],
+        help = "chapters to download, such as 1-10,15,20.5,v3-v5,latest:5,!13"
+    )]
+    select: Option<Selection>,
+    #[arg(
+        long,
+        help = "list chapters from the chapter feed instead of the aggregate"
+    )]
+    feed: bool,
+    #[arg(long, help = "scanlation group id or name to prefer, in order")]
+    prefer_group: Vec<String>,
+    #[arg(
+        long,
+        help = "scanlation group id or name to use when no preferred group has the chapter"
+    )]
+    fallback_group: Vec<String>,
+    #[arg(long, help = "scanlation group id or name to never download from")]
+    exclude_group: Vec<String>,
+    #[arg(long, help = "uploader to never download from")]
+    exclude_uploader: Vec<String>,
+    #[arg(
+        long,
+        help = "how to pick between releases of a chapter: newest, most-pages or official"
+    )]
+    prefer: Vec<Preference>,
+    #[arg(short, long, default_value = ".", help = "destination folder")]
+    path: PathBuf,
+    #[arg(
+        short = 'r',
+        long = "raw",
+        action=ArgAction::SetFalse,
+        default_value_t = true,
+        help = "download uncompressed images"
+    )]
+    data_saver: bool,
+    #[arg(long, default_value_t = 3, help = "number of retries for each page")]
+    retries: u32,
+    #[arg(
+        long,
+        help = "do not report image fetches to the MangaDex@Home network"
+    )]
+    no_report: bool,
+    #[arg(
+        short,
+        long,
+        default_value_t = 1,
+        help = "number of chapters downloaded in parallel"
+    )]
+    jobs: usize,
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY, help = "number of pages of a chapter downloaded in parallel")]
+    concurrency: usize,
+    #[arg(
+        long,
+        default_value_t = 16,
+        help = "maximum number of simultaneous page connections"
+    )]
+    connections: usize,
+    #[arg(long, help = "keep chapters with pages that failed to download")]
+    allow_incomplete: bool,
+    #[arg(long, conflicts_with = "package", help = "make cbz file")]
+    make_cbz: bool,
+    #[arg(
+        long,
+        help = "bundle chapters into cbz files: none, single, volume or chapter"
+    )]
+    package: Option<PackageMode>,
+    #[arg(
+        long,
+        help = "archive format: cbz, epub or pdf; implies --package single when not given"
+    )]
+    format: Option<PackageFormat>,
+    #[arg(
+        long,
+        help = "write pages straight into the cbz files while downloading, without chapter folders"
+    )]
+    stream: bool,
+    #[arg(
+        long,
+        help = "archive file name, using {series}, {volume}, {chapter} and {title}"
+    )]
+    name_template: Option<String>,
+    #[arg(
+        long,
+        help = "save volume covers next to the chapters and put them first in the cbz: original, 512 or 256"
+    )]
+    cover: Option<CoverSize>,
+    #[arg(
+        long,
+        help = "journal of the downloaded chapters, .mgdm-history.jsonl in the destination folder by default"
+    )]
+    history: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+#[group(
+    id = "chapter_range",
+    multiple = true,
+    conflicts_with = "range",
+    conflicts_with = "volume_range"
+)]
+struct ChapterRange {
+    #[arg(long)]
+    min_chapter: Option<f32>,
+    #[arg(long)]
+    max_chapter: Option<f32>,
+}
+
+#[derive(Debug, Clone, Args)]
+#[group(id = "volume_range", multiple = true, conflicts_with = "range")]
+struct VolumeRange {
+    #[arg(long)]
+    min_volume: Option<f32>,
+    #[arg(long)]
+    max_volume: Option<f32>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    // The update command tells the options given from the defaults
+    let matches = Arguments::command().get_matches();
+    let args = Arguments::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let options = match &args.command {
+        Some(Command::History(history)) => return history::run(history),
+        Some(Command::Search(search)) => &search.download,
+        Some(Command::Sync(sync)) => &sync.download,
+        Some(Command::Update(update)) => &update.download,
+        None => &args.download,
+    };
+    let mut client = MangadexClient::builder();
+    if options.no_report {
+        client = client.disable_reporting();
+    }
+    if let Some(Command::Sync(sync)) = &args.command {
+        client = client.auth(sync.auth.login());
+    }
+    let client = client.build()?;
+
+    match args.command {
+        Some(Command::Search(search)) => search::run(&client, search).await,
+        Some(Command::Sync(sync)) => sync::run(&client, sync).await,
+        Some(Command::Update(update)) => {
+            let matches = matches
+                .subcommand_matches("update")
+                .expect("update command");
+            update::run(&client, update, matches).await
+        }
+        Some(Command::History(_)) => unreachable!("history needs no client"),
+        None => download(&client, &args.manga.unwrap_or_default(), &args.download).await,
+    }
+}
+
+async fn download(
+    client: &MangadexClient,
+    manga: &str,
+    args: &DownloadOptions,
+) -> anyhow::Result<()> {
+    let mut query = if manga.contains("mangadex.org") {
+        MangaQuery::from_url(manga)?
+    } else {
+        MangaQuery::new(manga)
+    };
+
+    let info = MangaInfo::fetch(client, query.id()).await?;
+    query = query.language(&args.language);
+    for group in &args.groups {
+        query = query.group(group);
+    }
+
+    // Choosing between releases needs their metadata, which only the feed has
+    let policy = selection_policy(args);
+    let feed = if args.feed || policy.is_some() {
+        let mut feed = MangaFeed::new(info.id())
+            .language(&args.language)
+            .include_external(false);
+        for group in &args.groups {
+            feed = feed.group(group);
+        }
+        Some(feed.execute(client).await?)
+    } else {
+        None
+    };
+    let manga_volumes = match &feed {
+        Some(feed) if args.feed => Volume::from_feed(feed),
+        _ => query.execute(client).await?,
+    };
+
+    let failed = download_volumes(client, &info, &manga_volumes, feed.as_deref(), args).await?;
+    if !failed.is_empty() {
+        let names: Vec<&str> = failed.iter().map(|(_, name)| name.as_str()).collect();
+        anyhow::bail!(
+            "{} chapters failed to download: {}",
+            failed.len(),
+            names.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Download and package the chapters of `manga_volumes` picked by `args`,
+/// returning the id and name of the chapters that failed. Releases are chosen
+/// from `feed` when a selection policy is set.
+async fn download_volumes(
+    client: &MangadexClient,
+    info: &MangaInfo,
+    manga_volumes: &[Volume],
+    feed: Option<&[ChapterInfo]>,
+    args: &DownloadOptions,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let title = info.title(&[&args.language]);
+    println!("{title}");
+    let folder_name = info.folder_name(&[&args.language]);
+    let manga_path = args.path.join(&folder_name);
+
+    let policy = selection_policy(args);
+    let chapters = if let Some(selection) = &args.select {
+        selection.select(manga_volumes)
+    } else if !args.volumes.is_empty() {
+        let filtered_volumes: Vec<&Volume> = manga_volumes
+            .iter()
+            .filter(|x| args.volumes.contains(&x.volume().unwrap_or(f32::INFINITY)))
+            .collect();
+        filtered_volumes.get_chapters()
+    } else if !args.chapters.is_empty() {
+        manga_volumes
+            .get_chapters()
+            .into_iter()
+            .filter(|c| {
+                args.chapters
+                    .contains(&c.chapter().unwrap_or(f32::INFINITY))
+            })
+            .collect()
+    } else if args.chapter_range.min_chapter.is_some() || args.chapter_range.max_chapter.is_some() {
+        let min_chap = args.chapter_range.min_chapter.unwrap_or(f32::NEG_INFINITY);
+        let max_chap = args.chapter_range.max_chapter.unwrap_or(f32::INFINITY);
+        manga_volumes
+            .get_chapters()
+            .into_iter()
+            .filter(|c| {
+                let c = c.chapter().unwrap_or(-1.0);
+                c >= min_chap && c <= max_chap
+            })
+            .collect()
+    } else {
+        let min_vol = args.volume_range.min_volume.unwrap_or(f32::NEG_INFINITY);
+        let max_vol = args.volume_range.max_volume.unwrap_or(f32::INFINITY);
+        manga_volumes
+            .iter()
+            .filter(|v| {
+                let v = v.volume().unwrap_or(-1.0);
+                v >= min_vol && v <= max_vol
+            })
+            .get_chapters()
+    };
+
+    let chapters: Vec<(&String, Option<f32>)> = match (&policy, feed) {
+        (Some(policy), Some(feed)) => policy
+            .select(&chapters, feed)
+            .into_iter()
+            .map(|c| (c.id(), c.chapter_number()))
+            .collect(),
+        _ => chapters.iter().map(|c| (c.id(), *c.chapter())).collect(),
+    };
+
+    let width = chapters
+        .last()
+        .and_then(|(_, c)| c.as_ref())
+        .map(|&c| c.log10().floor() as usize)
+        .unwrap_or(0)
+        + 1;
+
+    let volume_of: HashMap<&String, Option<f32>> = manga_volumes
+        .iter()
+        .flat_map(|v| {
+            v.chapters().values().flat_map(move |c| {
+                std::iter::once(c.id())
+                    .chain(c.others())
+                    .map(move |id| (id, *v.volume()))
+            })
+        })
+        .collect();
+    let titles: HashMap<&String, &String> = feed
+        .into_iter()
+        .flatten()
+        .filter_map(|c| c.title().as_ref().map(|t| (c.id(), t)))
+        .collect();
+    let groups: HashMap<&String, Vec<String>> = feed
+        .into_iter()
+        .flatten()
+        .map(|c| {
+            (
+                c.id(),
+                c.groups().iter().map(|g| g.name().clone()).collect(),
+            )
+        })
+        .collect();
+
+    let mode = args
+        .package
+        .unwrap_or(if args.make_cbz || args.format.is_some() {
+            PackageMode::Single
+        } else {
+            PackageMode::None
+        });
+    let mut packager = Packager::new(mode, &folder_name)
+        .format(args.format.unwrap_or_default())
+        .comic_info(ComicInfo::from_manga(info, &[&args.language]).language(&args.language));
+    if let Some(template) = &args.name_template {
+        packager = packager.template(template);
+    }
+    if let Some(size) = args.cover {
+        let mut volumes: Vec<Option<f32>> = Vec::new();
+        for (id, _) in &chapters {
+            let volume = volume_of.get(id).copied().flatten();
+            if !volumes.contains(&volume) {
+                volumes.push(volume);
+            }
+        }
+        match download_covers(client, info, &volumes, &manga_path, size).await {
+            Ok(covers) => {
+                for (volume, path) in covers {
+                    packager = packager.cover(volume, path);
+                }
+            }
+            Err(e) => println!("Could not download covers: {e}"),
+        }
+    }
+    // One archive sits next to the series folder, several go inside it
+    let archive_dir = if mode == PackageMode::Single {
+        &args.path
+    } else {
+        &manga_path
+    };
+    let mut history = History::open(history_path(args))?;
+
+    let mut requests = Vec::new();
+    let mut labels = HashMap::new();
+    let mut queued = Vec::new();
+    let mut downloaded = Vec::new();
+    for (id, chapter) in chapters {
+        let chapter_name = match chapter {
+            Some(c) => format!("chapter_{c:0width$}", width = width),
+            // Chapters without a number would otherwise share a folder
+            None => format!("chapter_none_{}", id.get(..8).unwrap_or(id)),
+        };
+
+        // A chapter kept with pages missing is resumed where it was downloaded,
+        // even if the series was named differently then, and packaged again
+        let resumed = history.get(id).filter(|e| !e.is_complete());
+        let download_path = match resumed {
+            Some(entry) if entry.path.exists() => {
+                println!(
+                    "Resume {chapter_name}, {} pages missing in {}",
+                    entry.missing_pages().len(),
+                    entry.path.display()
+                );
+                entry.path.clone()
+            }
+            _ => chapter_path(id, &args.path, &manga_path, &chapter_name),
+        };
+        let mut package_chapter = PackageChapter::new(&download_path);
+        if let Some(c) = chapter {
+            package_chapter = package_chapter.chapter(format!("{c:0width$}"));
+        }
+        if let Some(Some(volume)) = volume_of.get(id) {
+            package_chapter = package_chapter.volume(volume);
+        }
+        if let Some(title) = titles.get(id) {
+            package_chapter = package_chapter.title(title);
+        }
+
+        // Another release of the chapter may have been downloaded before
+        let number = chapter.map(|c| c.to_string());
+        if let Some(entry) = history
+            .find(info.id(), number.as_deref(), &args.language)
+            .filter(|e| e.is_present() && e.is_complete())
+        {
+            let location = match &entry.archive {
+                Some(archive) if mode != PackageMode::None => Some(archive),
+                None if mode == PackageMode::None && entry.path != download_path => {
+                    Some(&entry.path)
+                }
+                _ => None,
+            };
+            if let Some(location) = location {
+                println!(
+                    "Skip {chapter_name}, already downloaded to {}",
+                    location.display()
+                );
+                continue;
+            }
+        }
+        if resumed.is_none() && packager.is_packaged(archive_dir, &package_chapter) {
+            println!("Skip {chapter_name}, already packaged");
+            continue;
+        }
+        if ChapterManifest::is_complete(&download_path) {
+            println!("Skip {chapter_name}, already downloaded");
+            downloaded.push(package_chapter);
+            continue;
+        }
+
+        labels.insert(id.clone(), chapter_name.clone());
+        requests.push(
+            ChapterDownloadRequest::new(id)
+                .data_saver(args.data_saver)
+                .path(&download_path)
+                .retry(RetryPolicy::new().max_retries(args.retries))
+                .concurrency(args.concurrency)
+                .allow_incomplete(args.allow_incomplete),
+        );
+        let entry = HistoryEntry {
+            title: title.to_string(),
+            chapter: number,
+            volume: volume_of.get(id).copied().flatten().map(|v| v.to_string()),
+            groups: groups.get(id).cloned().unwrap_or_default(),
+            language: args.language.clone(),
+            ..HistoryEntry::new(info.id(), id)
+        };
+        queued.push((entry, chapter_name, download_path));
+        downloaded.push(package_chapter);
+    }
+
+    let stream = if args.stream {
+        if mode == PackageMode::None {
+            anyhow::bail!("--stream needs --package, --format or --make-cbz");
+        }
+        fs::create_dir_all(archive_dir)?;
+        let mut stream = packager.stream(archive_dir, &downloaded)?;
+        // Chapters downloaded before go in from their folders
+        for chapter in &downloaded {
+            if !queued.iter().any(|(_, _, path)| path == chapter.path()) {
+                stream.copy_folder(chapter.path())?;
+            }
+        }
+        requests = requests
+            .into_iter()
+            .zip(&queued)
+            .map(|(request, (_, _, path))| match stream.sink(path) {
+                Some(sink) => request.sink(sink),
+                None => request,
+            })
+            .collect();
+        Some(stream)
+    } else {
+        None
+    };
+
+    let multi = MultiProgress::new();
+    let overall = multi.add(
+        ProgressBar::new(requests.len() as u64).with_style(
+            ProgressStyle::with_template("{prefix:>14} [{bar:30}] {pos}/{len} chapters")?
+                .progress_chars("=> "),
+        ),
+    );
+    overall.set_prefix("total");
+    let download_service = ServiceBuilder::new()
+        .concurrency_limit(args.jobs.max(1))
+        .rate_limit(1, Duration::from_secs(2))
+        .service(
+            ChapterDownloader::new(client.clone())
+                .max_connections(args.connections)
+                .progress(progress_listener(multi, overall.clone(), labels)),
+        );
+
+    // Responses come back in request order, keep going when a chapter fails
+    let mut failed = Vec::new();
+    let mut queued = queued.into_iter();
+    let mut downloads = download_service.call_all(futures::stream::iter(requests));
+    while let Some(result) = downloads.next().await {
+        let (entry, chapter_name, download_path) = queued.next().expect("one response per request");
+        match result {
+            Ok(report) => {
+                if !report.is_complete() {
+                    overall.println(format!(
+                        "{chapter_name}: kept with {} of {} pages missing",
+                        report.failed().len(),
+                        report.total_pages()
+                    ));
+                }
+                history.record(entry.downloaded(&report))?;
+            }
+            Err(e) => {
+                overall.println(format!("{chapter_name}: {e}"));
+                failed.push((entry.chapter_id, chapter_name, download_path));
+            }
+        }
+    }
+    overall.finish();
+
+    downloaded.retain(|c| !failed.iter().any(|(_, _, path)| path == c.path()));
+
+    if packager.mode() != PackageMode::None {
+        let archives = match stream {
+            Some(stream) => stream.finish()?,
+            None => {
+                println!("Packaging chapters...");
+                packager.package(archive_dir, &downloaded)?
+            }
+        };
+        for archive in archives {
+            println!("Wrote {}", archive.display());
+        }
+        let packaged: Vec<HistoryEntry> = downloaded
+            .iter()
+            .filter_map(|chapter| {
+                let archive = archive_dir.join(packager.archive_name(chapter)?);
+                let entry = history.entries().iter().find(|e| {
+                    e.manga == info.id() && e.archive.is_none() && e.path == chapter.path()
+                })?;
+                archive.exists().then(|| entry.clone().packaged(archive))
+            })
+            .collect();
+        for entry in packaged {
+            history.record(entry)?;
+        }
+        // Drop the series folder unless something else lives there
+        let _ = fs::remove_dir(&manga_path);
+        println!("Done.");
+    }
+
+    Ok(failed.into_iter().map(|(id, name, _)| (id, name)).collect())
+}
+
+/// Folder of chapter `id` inside `manga_path`. Earlier versions put chapters
+/// straight in `path`, a download of the same chapter left there is resumed in
+/// place.
+fn chapter_path(id: &str, path: &Path, manga_path: &Path, chapter_name: &str) -> PathBuf {
+    let chapter_path = manga_path.join(chapter_name);
+    let legacy_path = path.join(chapter_name);
+    let is_legacy = ChapterManifest::load(&legacy_path).is_some_and(|m| m.id() == id);
+    if !chapter_path.exists() && is_legacy {
+        return legacy_path;
+    }
+    chapter_path
+}
+
+struct ChapterBar {
+    bar: ProgressBar,
+    start: Instant,
+    received: u64,
+}
+
+/// Render one progress bar per chapter in flight, above the `overall` bar
+/// counting finished chapters. `labels` maps chapter ids to display names.
+fn progress_listener(
+    multi: MultiProgress,
+    overall: ProgressBar,
+    labels: HashMap<String, String>,
+) -> impl ProgressListener {
+    let style = ProgressStyle::with_template("{prefix:>14} [{bar:30}] {pos}/{len} {msg} eta {eta}")
+        .unwrap()
+        .progress_chars("=> ");
+    let bars: Mutex<HashMap<String, ChapterBar>> = Mutex::new(HashMap::new());
+    move |event| {
+        let mut bars = bars.lock().unwrap();
+        match event {
+            ProgressEvent::ChapterStarted {
+                id,
+                total_pages,
+                present_pages,
+            } => {
+                let bar = multi.insert_before(
+                    &overall,
+                    ProgressBar::new(total_pages as u64).with_style(style.clone()),
+                );
+                bar.set_prefix(labels.get(&id).cloned().unwrap_or_else(|| id.clone()));
+                bar.set_position(present_pages as u64);
+                bars.insert(
+                    id,
+                    ChapterBar {
+                        bar,
+                        start: Instant::now(),
+                        received: 0,
+                    },
+                );
+            }
+            ProgressEvent::PageDone {
+                id, done, bytes, ..
+            } => {
+                if let Some(chapter) = bars.get_mut(&id) {
+                    chapter.received += bytes as u64;
+                    let rate =
+                        chapter.received as f64 / chapter.start.elapsed().as_secs_f64().max(0.001);
+                    chapter.bar.set_position(done as u64);
+                    chapter
+                        .bar
+                        .set_message(format!("{}/s", HumanBytes(rate as u64)));
+                }
+            }
+            ProgressEvent::Retry {
+                id,
+                page,
+                attempt,
+                error,
+                ..
+            } => {
+                let label = labels.get(&id).unwrap_or(&id);
+                let _ = multi.println(format!(
+                    "{label}: retry page {page} (attempt {attempt}): {error}"
+                ));
+            }
+            ProgressEvent::ChapterFinished { id, success } => {
+                if let Some(chapter) = bars.remove(&id) {
+                    chapter.bar.finish_and_clear();
+                }
+                let label = labels.get(&id).unwrap_or(&id);
+                let status = if success { "done" } else { "failed" };
+                let _ = multi.println(format!("{label}: {status}"));
+                overall.inc(1);
+            }
+        }
+    }
+}
+
+fn history_path(args: &DownloadOptions) -> PathBuf {
+    args.history
+        .clone()
+        .unwrap_or_else(|| args.path.join(HISTORY_FILE_NAME))
+}
+
+/// Policy to choose between releases of a chapter, if any option asks for one.
+fn selection_policy(args: &DownloadOptions) -> Option<SelectionPolicy> {
+    if args.prefer_group.is_empty()
+        && args.fallback_group.is_empty()
+        && args.exclude_group.is_empty()
+        && args.exclude_uploader.is_empty()
+        && args.prefer.is_empty()
+    {
+        return None;
+    }
+    let mut policy = SelectionPolicy::new();
+    for group in &args.prefer_group {
+        policy = policy.preferred_group(group);
+    }
+    for group in &args.fallback_group {
+        policy = policy.fallback_group(group);
+    }
+    for group in &args.exclude_group {
+        policy = policy.exclude_group(group);
+    }
+    for uploader in &args.exclude_uploader {
+        policy = policy.exclude_uploader(uploader);
+    }
+    for preference in &args.prefer {
+        policy = policy.prefer(*preference);
+    }
+    Some(policy)
+}
+
+/// Save the cover of each of `volumes` in `manga_path`, keyed by volume. The
+/// main cover is kept under `None`, for volumes without a cover of their own.
+async fn download_covers(
+    client: &MangadexClient,
+    info: &MangaInfo,
+    volumes: &[Option<f32>],
+    manga_path: &Path,
+    size: CoverSize,
+) -> anyhow::Result<Vec<(Option<f32>, PathBuf)>> {
+    let covers = CoverQuery::new(info.id()).execute(client).await?;
+    let main_cover = covers
+        .iter()
+        .find(|c| info.cover().as_ref() == Some(c.file_name()));
+    let mut wanted: Vec<(Option<f32>, &Cover)> = volumes
+        .iter()
+        .filter(|v| v.is_some())
+        .filter_map(|&v| {
+            covers
+                .iter()
+                .find(|c| c.volume_number() == v)
+                .map(|c| (v, c))
+        })
+        .collect();
+    if let Some(cover) = main_cover {
+        wanted.push((None, cover));
+    }
+
+    let mut saved = Vec::new();
+    for (volume, cover) in wanted {
+        let name = match volume {
+            Some(v) => format!("cover_volume_{v}"),
+            None => String::from("cover"),
+        };
+        let path = manga_path.join(format!("{name}.{}", cover.extension(size)));
+        if !path.exists() {
+            fs::create_dir_all(manga_path)?;
+            fs::write(&path, cover.download(client, size).await?)?;
+        }
+        saved.push((volume, path));
+    }
+    Ok(saved)
+}
@@ -0,0 +1,161 @@
+use std::io::BufRead;
+use std::io::Write;
+
+use clap::Args;
+use mangadex::{
+    fetch_tags, ContentRating, Demographic, Manga, MangaSearch, MangaStatus, MangadexClient,
+    OrderBy, OrderDirection, Tag,
+};
+
+use super::DownloadOptions;
+
+const TITLE_WIDTH: usize = 50;
+
+#[derive(Debug, Args)]
+pub struct SearchArgs {
+    #[arg(help = "title to search for")]
+    title: Option<String>,
+    #[arg(long, help = "author id")]
+    author: Vec<String>,
+    #[arg(long, help = "artist id")]
+    artist: Vec<String>,
+    #[arg(long, help = "tag name the manga must have")]
+    tag: Vec<String>,
+    #[arg(long, help = "tag name the manga must not have")]
+    exclude_tag: Vec<String>,
+    #[arg(long, help = "publication status")]
+    status: Vec<MangaStatus>,
+    #[arg(long, help = "publication demographic")]
+    demographic: Vec<Demographic>,
+    #[arg(long, help = "content rating")]
+    rating: Vec<ContentRating>,
+    #[arg(long, help = "year of release")]
+    year: Option<u32>,
+    #[arg(long, help = "sort results by this field")]
+    order: Option<OrderBy>,
+    #[arg(long, default_value = "desc", help = "sort direction")]
+    direction: OrderDirection,
+    #[arg(long, default_value_t = 10, help = "number of results")]
+    limit: usize,
+    #[arg(long, default_value_t = 0, help = "number of results to skip")]
+    offset: usize,
+    #[command(flatten)]
+    pub download: DownloadOptions,
+}
+
+pub async fn run(client: &MangadexClient, args: SearchArgs) -> anyhow::Result<()> {
+    let mut search = MangaSearch::new()
+        .language(&args.download.language)
+        .limit(args.limit)
+        .offset(args.offset);
+    if let Some(title) = &args.title {
+        search = search.title(title);
+    }
+    for author in &args.author {
+        search = search.author(author);
+    }
+    for artist in &args.artist {
+        search = search.artist(artist);
+    }
+    if !args.tag.is_empty() || !args.exclude_tag.is_empty() {
+        let tags = fetch_tags(client).await?;
+        for name in &args.tag {
+            search = search.included_tag(find_tag(&tags, name)?.id());
+        }
+        for name in &args.exclude_tag {
+            search = search.excluded_tag(find_tag(&tags, name)?.id());
+        }
+    }
+    for status in &args.status {
+        search = search.status(*status);
+    }
+    for demographic in &args.demographic {
+        search = search.demographic(*demographic);
+    }
+    for rating in &args.rating {
+        search = search.content_rating(*rating);
+    }
+    if let Some(year) = args.year {
+        search = search.year(year);
+    }
+    if let Some(order) = args.order {
+        search = search.order(order, args.direction);
+    }
+
+    let result = search.execute(client).await?;
+    if result.data().is_empty() {
+        println!("No manga found");
+        return Ok(());
+    }
+    print_table(result.data(), &args.download.language);
+    if result.has_more() {
+        println!(
+            "{} results in total, use --offset {} to see more",
+            result.total(),
+            result.offset() + result.data().len()
+        );
+    }
+
+    let Some(manga) = pick(result.data())? else {
+        return Ok(());
+    };
+    println!("Downloading {}", manga.title(&[&args.download.language]));
+    super::download(client, manga.id(), &args.download).await
+}
+
+fn find_tag<'a>(tags: &'a [Tag], name: &str) -> anyhow::Result<&'a Tag> {
+    tags.iter()
+        .find(|t| t.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| anyhow::anyhow!("unknown tag '{name}'"))
+}
+
+fn print_table(manga: &[Manga], language: &str) {
+    println!(
+        "{:>3}  {:<TITLE_WIDTH$}  {:>4}  {:<9}  id",
+        "#", "title", "year", "status"
+    );
+    for (i, m) in manga.iter().enumerate() {
+        let attributes = m.attributes();
+        let mut title = m.title(&[language]).to_string();
+        if title.chars().count() > TITLE_WIDTH {
+            title = title.chars().take(TITLE_WIDTH - 1).collect::<String>() + "…";
+        }
+        let year = attributes.year().map(|y| y.to_string()).unwrap_or_default();
+        let status = attributes
+            .status()
+            .map(|s| s.to_string())
+            .unwrap_or_default();
+        println!(
+            "{:>3}  {:<TITLE_WIDTH$}  {:>4}  {:<9}  {}",
+            i + 1,
+            title,
+            year,
+            status,
+            m.id()
+        );
+    }
+}
+
+/// Ask which manga to download, `None` when the answer is empty.
+fn pick(manga: &[Manga]) -> anyhow::Result<Option<&Manga>> {
+    let stdin = std::io::stdin();
+    loop {
+        print!(
+            "Pick a manga to download [1-{}], or nothing to quit: ",
+            manga.len()
+        );
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        match line.parse::<usize>() {
+            Ok(i) if (1..=manga.len()).contains(&i) => return Ok(Some(&manga[i - 1])),
+            _ => println!("'{line}' is not a number between 1 and {}", manga.len()),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::PathBuf;
+
+use clap::Args;
+use mangadex::{
+    chapters_by_manga, FollowState, MangaInfo, MangadexAuth, MangadexClient, Volume,
+    DEFAULT_TOKEN_URL, FOLLOW_STATE_FILE_NAME,
+};
+
+use super::DownloadOptions;
+
+#[derive(Debug, Args)]
+pub struct SyncArgs {
+    #[arg(
+        long,
+        help = "fetch chapters published since this date or time instead of since the last sync, in UTC unless an offset is given, as 2023-05-01, 2023-05-01T12:00:00 or 2023-05-01T21:00:00+09:00"
+    )]
+    since: Option<String>,
+    #[arg(
+        long,
+        help = "file recording the last sync, .mgdm-follows.json in the destination folder by default"
+    )]
+    state: Option<PathBuf>,
+    #[command(flatten)]
+    pub auth: AuthOptions,
+    #[command(flatten)]
+    pub download: DownloadOptions,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct AuthOptions {
+    #[arg(long, env = "MANGADEX_CLIENT_ID", help = "personal API client id")]
+    client_id: String,
+    #[arg(
+        long,
+        env = "MANGADEX_CLIENT_SECRET",
+        hide_env_values = true,
+        help = "personal API client secret"
+    )]
+    client_secret: String,
+    #[arg(long, env = "MANGADEX_USERNAME", help = "mangadex account name")]
+    username: String,
+    #[arg(
+        long,
+        env = "MANGADEX_PASSWORD",
+        hide_env_values = true,
+        help = "mangadex account password"
+    )]
+    password: String,
+    #[arg(long, env = "MANGADEX_TOKEN_URL", default_value = DEFAULT_TOKEN_URL, help = "login endpoint")]
+    token_url: String,
+}
+
+impl AuthOptions {
+    pub fn login(&self) -> MangadexAuth {
+        MangadexAuth::new(&self.client_id, &self.client_secret)
+            .password(&self.username, &self.password)
+            .token_url(&self.token_url)
+    }
+}
+
+pub async fn run(client: &MangadexClient, args: SyncArgs) -> anyhow::Result<()> {
+    let options = &args.download;
+    let state_path = args
+        .state
+        .clone()
+        .unwrap_or_else(|| options.path.join(FOLLOW_STATE_FILE_NAME));
+    let mut state = match (&args.since, FollowState::load(&state_path)?) {
+        (Some(since), _) => FollowState::starting_at(since),
+        (None, Some(state)) => state,
+        (None, None) => anyhow::bail!(
+            "nothing synced to {} yet, use --since to choose where to start",
+            state_path.display()
+        ),
+    };
+
+    let mut feed = state
+        .feed()
+        .language(&options.language)
+        .include_external(false);
+    for group in &options.groups {
+        feed = feed.group(group);
+    }
+    let chapters = state.pending(feed.execute(client).await?);
+    if chapters.is_empty() {
+        println!("No new chapters");
+    }
+
+    // A manga that cannot be fetched at all counts as all its chapters failed
+    let mut failed = Vec::new();
+    for (manga, new_chapters) in chapters_by_manga(chapters.clone()) {
+        let result = match MangaInfo::fetch(client, &manga).await {
+            Ok(info) => {
+                let volumes = Volume::from_feed(&new_chapters);
+                super::download_volumes(client, &info, &volumes, Some(&new_chapters), options).await
+            }
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok(chapters) => failed.extend(chapters),
+            Err(e) => {
+                println!("{manga}: {e}");
+                failed.extend(
+                    new_chapters
+                        .iter()
+                        .map(|c| (c.id().clone(), format!("{manga}/{}", c.id()))),
+                );
+            }
+        }
+    }
+
+    let failed_ids: Vec<String> = failed.iter().map(|(id, _)| id.clone()).collect();
+    state.advance(&chapters, &failed_ids);
+    if let Some(parent) = state_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    state.save(&state_path)?;
+
+    if !failed.is_empty() {
+        let names: Vec<&str> = failed.iter().map(|(_, name)| name.as_str()).collect();
+        anyhow::bail!(
+            "{} chapters failed to download: {}",
+            failed.len(),
+            names.join(", ")
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args};
+use mangadex::MangadexClient;
+use serde::Deserialize;
+
+use super::DownloadOptions;
+
+#[derive(Debug, Args)]
+pub struct UpdateArgs {
+    #[arg(
+        default_value = "mgdm.toml",
+        help = "library file listing the manga to keep up to date"
+    )]
+    library: PathBuf,
+    #[command(flatten)]
+    pub download: DownloadOptions,
+}
+
+/// Manga kept up to date by `mgdm update`, read from a TOML file such as
+///
+/// ```toml
+/// [defaults]
+/// path = "manga"
+/// package = "volume"
+///
+/// [[manga]]
+/// id = "99b8eaeb-9041-4bfd-8eb7-d72addc88eb7"
+/// groups = ["a6a0bb8e-8d9f-4d7f-bd24-6d5bde5b3a04"]
+/// format = "epub"
+/// select = "latest:10"
+/// ```
+///
+/// Settings of an entry take precedence over the defaults, and options given on
+/// the command line over both. A `select` is ignored when chapters are chosen
+/// on the command line. Relative paths are relative to the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Library {
+    defaults: Settings,
+    manga: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Settings {
+    language: Option<String>,
+    groups: Option<Vec<String>>,
+    path: Option<PathBuf>,
+    package: Option<String>,
+    format: Option<String>,
+    name_template: Option<String>,
+    cover: Option<String>,
+    select: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    /// Manga id or url
+    id: String,
+    language: Option<String>,
+    groups: Option<Vec<String>>,
+    path: Option<PathBuf>,
+    package: Option<String>,
+    format: Option<String>,
+    name_template: Option<String>,
+    cover: Option<String>,
+    select: Option<String>,
+}
+
+/// Arguments choosing the chapters on the command line.
+const SELECTION_ARGS: [&str; 7] = [
+    "select",
+    "chapters",
+    "volumes",
+    "min_chapter",
+    "max_chapter",
+    "min_volume",
+    "max_volume",
+];
+
+impl Library {
+    /// Download options of every entry, starting from `cli` where `given`
+    /// tells which arguments were on the command line.
+    fn options(
+        &self,
+        cli: &DownloadOptions,
+        given: &dyn Fn(&str) -> bool,
+        base: &Path,
+    ) -> anyhow::Result<Vec<DownloadOptions>> {
+        let mut defaults = cli.clone();
+        self.defaults
+            .apply(&mut defaults, given, base)
+            .context("invalid defaults")?;
+        self.manga
+            .iter()
+            .map(|entry| {
+                let mut options = defaults.clone();
+                entry
+                    .settings()
+                    .apply(&mut options, given, base)
+                    .with_context(|| format!("invalid settings for {}", entry.id))?;
+                Ok(options)
+            })
+            .collect()
+    }
+}
+
+impl Settings {
+    /// Override the options that were not `given` on the command line.
+    fn apply(
+        &self,
+        options: &mut DownloadOptions,
+        given: &dyn Fn(&str) -> bool,
+        base: &Path,
+    ) -> anyhow::Result<()> {
+        if let Some(language) = self.language.as_ref().filter(|_| !given("language")) {
+            options.language = language.clone();
+        }
+        if let Some(groups) = self.groups.as_ref().filter(|_| !given("groups")) {
+            options.groups = groups.clone();
+        }
+        if let Some(path) = self.path.as_ref().filter(|_| !given("path")) {
+            options.path = base.join(path);
+        }
+        if let Some(package) = self
+            .package
+            .as_ref()
+            .filter(|_| !given("package") && !given("make_cbz"))
+        {
+            options.package = Some(package.parse().map_err(anyhow::Error::msg)?);
+        }
+        if let Some(format) = self.format.as_ref().filter(|_| !given("format")) {
+            options.format = Some(format.parse().map_err(anyhow::Error::msg)?);
+        }
+        if let Some(template) = self
+            .name_template
+            .as_ref()
+            .filter(|_| !given("name_template"))
+        {
+            options.name_template = Some(template.clone());
+        }
+        if let Some(cover) = self.cover.as_ref().filter(|_| !given("cover")) {
+            options.cover = Some(cover.parse().map_err(anyhow::Error::msg)?);
+        }
+        if let Some(select) = self
+            .select
+            .as_ref()
+            .filter(|_| !SELECTION_ARGS.iter().any(|id| given(id)))
+        {
+            options.select = Some(select.parse().map_err(anyhow::Error::msg)?);
+        }
+        Ok(())
+    }
+}
+
+impl Entry {
+    fn settings(&self) -> Settings {
+        Settings {
+            language: self.language.clone(),
+            groups: self.groups.clone(),
+            path: self.path.clone(),
+            package: self.package.clone(),
+            format: self.format.clone(),
+            name_template: self.name_template.clone(),
+            cover: self.cover.clone(),
+            select: self.select.clone(),
+        }
+    }
+}
+
+/// Whether an argument, by id, is in `matches` other than by its default.
+fn given(matches: &ArgMatches) -> impl Fn(&str) -> bool + '_ {
+    |id| {
+        matches
+            .value_source(id)
+            .is_some_and(|source| source != ValueSource::DefaultValue)
+    }
+}
+
+/// Update the manga of the library, `matches` being those of the `update`
+/// command line.
+pub async fn run(
+    client: &MangadexClient,
+    args: UpdateArgs,
+    matches: &ArgMatches,
+) -> anyhow::Result<()> {
+    let text = fs::read_to_string(&args.library)
+        .with_context(|| format!("cannot read {}", args.library.display()))?;
+    let library: Library = toml::from_str(&text)
+        .with_context(|| format!("invalid library {}", args.library.display()))?;
+    let base = args.library.parent().unwrap_or(Path::new(""));
+
+    let options = library.options(&args.download, &given(matches), base)?;
+    let entries = library.manga.iter().zip(options);
+
+    // Chapters already packaged or fully downloaded are skipped, so only the
+    // ones released since the last update are fetched
+    let mut failed = Vec::new();
+    for (entry, options) in entries {
+        if let Err(e) = super::download(client, &entry.id, &options).await {
+            println!("{}: {e}", entry.id);
+            failed.push(entry.id.as_str());
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "{} of {} manga failed to update: {}",
+            failed.len(),
+            library.manga.len(),
+            failed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::FromArgMatches;
+    use mangadex::{CoverSize, PackageFormat, PackageMode};
+
+    const LIBRARY: &str = r#"
+        [defaults]
+        language = "fr"
+        path = "manga"
+        package = "volume"
+        select = "latest:5"
+
+        [[manga]]
+        id = "first"
+
+        [[manga]]
+        id = "second"
+        path = "/srv/manga"
+        package = "chapter"
+        format = "epub"
+        cover = "512"
+    "#;
+
+    /// Options of every entry of `library` for the `update` command line `cli`.
+    fn options(library: &str, cli: &[&str]) -> anyhow::Result<Vec<DownloadOptions>> {
+        let matches = UpdateArgs::augment_args(clap::Command::new("update"))
+            .try_get_matches_from(std::iter::once("update").chain(cli.iter().copied()))?;
+        let args = UpdateArgs::from_arg_matches(&matches)?;
+        let library: Library = toml::from_str(library)?;
+        let options = library.options(&args.download, &given(&matches), Path::new("/library"));
+        options
+    }
+
+    #[test]
+    fn test_library_settings() {
+        let options = options(LIBRARY, &[]).unwrap();
+        assert_eq!(options.len(), 2);
+        assert_eq!(options[0].language, "fr");
+        assert_eq!(options[0].path, Path::new("/library/manga"));
+        assert_eq!(options[0].package, Some(PackageMode::PerVolume));
+        assert!(options[0].select.is_some());
+        assert_eq!(options[1].language, "fr");
+        assert_eq!(options[1].path, Path::new("/srv/manga"));
+        assert_eq!(options[1].package, Some(PackageMode::PerChapter));
+        assert_eq!(options[1].format, Some(PackageFormat::Epub));
+        assert_eq!(options[1].cover, Some(CoverSize::Medium));
+    }
+
+    #[test]
+    fn test_command_line_wins() {
+        let cli = [
+            "--language",
+            "de",
+            "--path",
+            "out",
+            "--package",
+            "single",
+            "--chapters",
+            "3",
+        ];
+        for options in options(LIBRARY, &cli).unwrap() {
+            assert_eq!(options.language, "de");
+            assert_eq!(options.path, Path::new("out"));
+            assert_eq!(options.package, Some(PackageMode::Single));
+            assert_eq!(options.chapters, [3.0]);
+            assert!(options.select.is_none());
+        }
+
+        let options = options(LIBRARY, &["--make-cbz", "--min-volume", "2"]).unwrap();
+        assert_eq!(options[1].package, None);
+        assert!(options[1].select.is_none());
+        assert_eq!(options[1].format, Some(PackageFormat::Epub));
+    }
+
+    #[test]
+    fn test_invalid_library() {
+        let unknown = "[[manga]]\nid = \"first\"\nchapter = 3\n";
+        assert!(options(unknown, &[]).is_err());
+        assert!(options("[defaults]\nlanguages = [\"en\"]\n", &[]).is_err());
+        let invalid = "[[manga]]\nid = \"first\"\npackage = \"weekly\"\n";
+        let error = format!("{:#}", options(invalid, &[]).unwrap_err());
+        assert!(error.starts_with("invalid settings for first"), "{error}");
+    }
+}
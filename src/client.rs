@@ -0,0 +1,248 @@
+use super::auth::MangadexAuth;
+use super::report::DEFAULT_REPORT_URL;
+use super::MangadexError;
+use reqwest::IntoUrl;
+use reqwest::RequestBuilder;
+use reqwest::Url;
+use std::time::Duration;
+
+pub const DEFAULT_BASE_URL: &str = "https://api.mangadex.org";
+pub const DEFAULT_UPLOADS_URL: &str = "https://uploads.mangadex.org";
+pub const DEFAULT_USER_AGENT: &str = "mgdcli";
+
+/// Handle to the mangadex API shared by every query and download.
+///
+/// It owns a single connection-pooled `reqwest::Client`, so cloning it is cheap
+/// and all clones reuse the same connections.
+#[derive(Debug, Clone)]
+pub struct MangadexClient {
+    http: reqwest::Client,
+    base_url: String,
+    uploads_url: String,
+    report_url: Option<String>,
+    auth: Option<MangadexAuth>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MangadexClientBuilder {
+    base_url: String,
+    uploads_url: String,
+    report_url: Option<String>,
+    auth: Option<MangadexAuth>,
+    user_agent: String,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+}
+
+impl MangadexClient {
+    pub fn new() -> Self {
+        Self::builder()
+            .build()
+            .expect("default client configuration is valid")
+    }
+
+    pub fn builder() -> MangadexClientBuilder {
+        MangadexClientBuilder::default()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Server hosting cover art.
+    pub fn uploads_url(&self) -> &str {
+        &self.uploads_url
+    }
+
+    /// Where MangaDex@Home fetches are reported, `None` when reporting is off.
+    pub fn report_url(&self) -> Option<&str> {
+        self.report_url.as_deref()
+    }
+
+    /// Login attached to API requests, `None` for anonymous access.
+    pub fn auth(&self) -> Option<&MangadexAuth> {
+        self.auth.as_ref()
+    }
+
+    pub fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    pub(crate) fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path.trim_start_matches('/'))
+    }
+
+    /// Request to the API, with a bearer token when logged in.
+    pub(crate) async fn get(&self, path: &str) -> Result<RequestBuilder, MangadexError> {
+        let request = self.http.get(self.endpoint(path));
+        match &self.auth {
+            Some(auth) => Ok(request.bearer_auth(auth.access_token(&self.http).await?)),
+            None => Ok(request),
+        }
+    }
+
+    pub(crate) fn get_url(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.http.get(url)
+    }
+}
+
+impl Default for MangadexClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for MangadexClientBuilder {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            uploads_url: DEFAULT_UPLOADS_URL.to_string(),
+            report_url: Some(DEFAULT_REPORT_URL.to_string()),
+            auth: None,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            timeout: None,
+            connect_timeout: None,
+        }
+    }
+}
+
+impl MangadexClientBuilder {
+    pub fn base_url(mut self, base_url: impl ToString) -> Self {
+        self.base_url = base_url.to_string();
+        self
+    }
+
+    pub fn uploads_url(mut self, uploads_url: impl ToString) -> Self {
+        self.uploads_url = uploads_url.to_string();
+        self
+    }
+
+    pub fn report_url(mut self, report_url: impl ToString) -> Self {
+        self.report_url = Some(report_url.to_string());
+        self
+    }
+
+    /// Stop reporting image fetches to the MangaDex@Home network.
+    pub fn disable_reporting(mut self) -> Self {
+        self.report_url = None;
+        self
+    }
+
+    /// Log in API requests with `auth`.
+    pub fn auth(mut self, auth: MangadexAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl ToString) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn build(self) -> Result<MangadexClient, MangadexError> {
+        let base_url = Url::parse(&self.base_url)
+            .map_err(|_e| MangadexError::UrlParseError(self.base_url.clone()))?;
+        let uploads_url = Url::parse(&self.uploads_url)
+            .map_err(|_e| MangadexError::UrlParseError(self.uploads_url.clone()))?;
+        if let Some(report_url) = &self.report_url {
+            Url::parse(report_url)
+                .map_err(|_e| MangadexError::UrlParseError(report_url.clone()))?;
+        }
+        if let Some(auth) = &self.auth {
+            Url::parse(auth.token_endpoint())
+                .map_err(|_e| MangadexError::UrlParseError(auth.token_endpoint().to_string()))?;
+        }
+
+        let mut builder = reqwest::Client::builder().user_agent(&self.user_agent);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        Ok(MangadexClient {
+            http: builder.build()?,
+            base_url: base_url.as_str().trim_end_matches('/').to_string(),
+            uploads_url: uploads_url.as_str().trim_end_matches('/').to_string(),
+            report_url: self.report_url,
+            auth: self.auth,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use serde_json::json;
+
+    #[test]
+    fn test_endpoint() {
+        let client = MangadexClient::new();
+        assert_eq!(
+            client.endpoint("/manga/abc/aggregate"),
+            "https://api.mangadex.org/manga/abc/aggregate"
+        );
+
+        let client = MangadexClient::builder()
+            .base_url("http://127.0.0.1:8080/mirror/")
+            .build()
+            .unwrap();
+        assert_eq!(
+            client.endpoint("at-home/server/abc"),
+            "http://127.0.0.1:8080/mirror/at-home/server/abc"
+        );
+
+        assert!(MangadexClient::builder()
+            .base_url("not a url")
+            .build()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token() {
+        let server = MockServer::start().await;
+        server.mount(
+            "/token",
+            MockResponse::json(json!({"access_token": "abc", "expires_in": 900})),
+        );
+        server.mount("/user/me", MockResponse::json(json!({"result": "ok"})));
+
+        server
+            .client()
+            .get("user/me")
+            .await
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        let auth = MangadexAuth::new("id", "secret")
+            .password("reader", "hunter2")
+            .token_url(format!("{}/token", server.url()));
+        let client = MangadexClient::builder()
+            .base_url(server.url())
+            .auth(auth)
+            .build()
+            .unwrap();
+        client.get("user/me").await.unwrap().send().await.unwrap();
+
+        let requests: Vec<_> = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.path() == "/user/me")
+            .collect();
+        assert_eq!(requests[0].header("authorization"), None);
+        assert_eq!(requests[1].header("authorization"), Some("Bearer abc"));
+    }
+}
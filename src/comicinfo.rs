@@ -0,0 +1,265 @@
+use super::info::MangaInfo;
+use std::fmt::Write;
+
+pub const COMIC_INFO_FILE_NAME: &str = "ComicInfo.xml";
+
+/// Metadata read by comic servers and readers such as Komga, Kavita or
+/// Tachiyomi, stored as `ComicInfo.xml` at the root of an archive.
+#[derive(Debug, Clone, Default)]
+pub struct ComicInfo {
+    pub(crate) title: Option<String>,
+    pub(crate) series: String,
+    pub(crate) number: Option<String>,
+    pub(crate) volume: Option<String>,
+    pub(crate) summary: Option<String>,
+    pub(crate) notes: Option<String>,
+    pub(crate) year: Option<u32>,
+    pub(crate) writer: Vec<String>,
+    pub(crate) penciller: Vec<String>,
+    pub(crate) genre: Vec<String>,
+    pub(crate) web: Option<String>,
+    pub(crate) language: Option<String>,
+    pub(crate) right_to_left: bool,
+    pub(crate) pages: Vec<ComicPage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComicPage {
+    pub size: u64,
+    pub kind: PageKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PageKind {
+    FrontCover,
+    #[default]
+    Story,
+}
+
+impl ComicInfo {
+    pub fn new(series: impl ToString) -> Self {
+        Self {
+            series: series.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Series-wide metadata of `info`, texts in the first of `languages`
+    /// available.
+    pub fn from_manga(info: &MangaInfo, languages: &[&str]) -> Self {
+        let manga = info.manga();
+        let attributes = manga.attributes();
+        Self {
+            series: info.title(languages).to_string(),
+            summary: manga.description(languages).map(str::to_string),
+            year: *attributes.year(),
+            writer: info.authors().clone(),
+            penciller: info.artists().clone(),
+            genre: info.tags().into_iter().map(str::to_string).collect(),
+            web: Some(format!("https://mangadex.org/title/{}", info.id())),
+            right_to_left: attributes.original_language().as_deref() == Some("ja"),
+            ..Self::default()
+        }
+    }
+
+    pub fn title(mut self, title: impl ToString) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn number(mut self, number: impl ToString) -> Self {
+        self.number = Some(number.to_string());
+        self
+    }
+
+    pub fn volume(mut self, volume: impl ToString) -> Self {
+        self.volume = Some(volume.to_string());
+        self
+    }
+
+    /// ISO code of the language the pages are in.
+    pub fn language(mut self, language: impl ToString) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    /// Free text, such as the pages missing from the archive.
+    pub fn notes(mut self, notes: impl ToString) -> Self {
+        self.notes = Some(notes.to_string());
+        self
+    }
+
+    /// Append a page, pages being listed in reading order.
+    pub fn page(mut self, size: u64, kind: PageKind) -> Self {
+        self.pages.push(ComicPage { size, kind });
+        self
+    }
+
+    /// Series name followed by the chapter or volume and the title, for
+    /// formats that have a single title field.
+    pub(crate) fn book_title(&self) -> String {
+        let mut title = self.series.clone();
+        if let Some(number) = &self.number {
+            let _ = write!(title, " - Chapter {number}");
+        } else if let Some(volume) = &self.volume {
+            let _ = write!(title, " - Volume {volume}");
+        }
+        if let Some(chapter_title) = &self.title {
+            let _ = write!(title, ": {chapter_title}");
+        }
+        title
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+            "<ComicInfo xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" ",
+            "xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n"
+        ));
+        let year = self.year.map(|y| y.to_string());
+        let page_count = (!self.pages.is_empty()).then(|| self.pages.len().to_string());
+        let manga = if self.right_to_left {
+            "YesAndRightToLeft"
+        } else {
+            "Yes"
+        };
+        // Elements must follow the order of the schema
+        let elements = [
+            ("Title", self.title.clone()),
+            ("Series", Some(self.series.clone())),
+            ("Number", self.number.clone()),
+            ("Volume", self.volume.clone()),
+            ("Summary", self.summary.clone()),
+            ("Notes", self.notes.clone()),
+            ("Year", year),
+            ("Writer", join(&self.writer)),
+            ("Penciller", join(&self.penciller)),
+            ("Genre", join(&self.genre)),
+            ("Web", self.web.clone()),
+            ("PageCount", page_count),
+            ("LanguageISO", self.language.clone()),
+            ("Manga", Some(manga.to_string())),
+        ];
+        for (name, value) in elements {
+            if let Some(value) = value {
+                let _ = writeln!(xml, "  <{name}>{}</{name}>", escape(&value));
+            }
+        }
+        if !self.pages.is_empty() {
+            xml.push_str("  <Pages>\n");
+            for (i, page) in self.pages.iter().enumerate() {
+                let kind = match page.kind {
+                    PageKind::FrontCover => "FrontCover",
+                    PageKind::Story => "Story",
+                };
+                let _ = writeln!(
+                    xml,
+                    "    <Page Image=\"{i}\" ImageSize=\"{}\" Type=\"{kind}\" />",
+                    page.size
+                );
+            }
+            xml.push_str("  </Pages>\n");
+        }
+        xml.push_str("</ComicInfo>\n");
+        xml
+    }
+}
+
+fn join(values: &[String]) -> Option<String> {
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+/// Notes of the `ComicInfo.xml` document `xml`.
+pub(crate) fn read_notes(xml: &str) -> Option<String> {
+    let (_, rest) = xml.split_once("<Notes>")?;
+    let (notes, _) = rest.split_once("</Notes>")?;
+    Some(unescape(notes))
+}
+
+pub(crate) fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+pub(crate) fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::Manga;
+
+    #[test]
+    fn test_comic_info_from_manga() {
+        let response: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/manga.json")).unwrap();
+        let manga: Manga = serde_json::from_value(response["data"].clone()).unwrap();
+        let xml = ComicInfo::from_manga(&MangaInfo::from(manga), &["en"]).to_xml();
+        assert!(xml.contains("<Series>The Café Terrace and Its Goddesses</Series>"));
+        assert!(xml.contains(
+            "<Summary>Hayato returns home to sell his late grandmother&apos;s café.</Summary>"
+        ));
+        assert!(xml.contains("<Year>2021</Year>"));
+        assert!(xml.contains("<Writer>Seo Kouji</Writer>"));
+        assert!(xml.contains("<Penciller>Seo Kouji</Penciller>"));
+        assert!(xml.contains("<Genre>Romance, Comedy</Genre>"));
+        assert!(xml.contains(
+            "<Web>https://mangadex.org/title/99b8eaeb-9041-4bfd-8eb7-d72addc88eb7</Web>"
+        ));
+        assert!(xml.contains("<Manga>YesAndRightToLeft</Manga>"));
+        assert!(!xml.contains("<Pages>"));
+    }
+
+    #[test]
+    fn test_comic_info_xml() {
+        let info = ComicInfo::new("Love & <Coffee>")
+            .number(2.5)
+            .volume(1)
+            .title("Mocha")
+            .notes("Missing <page 3>")
+            .language("en")
+            .page(1200, PageKind::FrontCover)
+            .page(3400, PageKind::Story);
+        assert_eq!(
+            info.to_xml(),
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+                "<ComicInfo xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" ",
+                "xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n",
+                "  <Title>Mocha</Title>\n",
+                "  <Series>Love &amp; &lt;Coffee&gt;</Series>\n",
+                "  <Number>2.5</Number>\n",
+                "  <Volume>1</Volume>\n",
+                "  <Notes>Missing &lt;page 3&gt;</Notes>\n",
+                "  <PageCount>2</PageCount>\n",
+                "  <LanguageISO>en</LanguageISO>\n",
+                "  <Manga>Yes</Manga>\n",
+                "  <Pages>\n",
+                "    <Page Image=\"0\" ImageSize=\"1200\" Type=\"FrontCover\" />\n",
+                "    <Page Image=\"1\" ImageSize=\"3400\" Type=\"Story\" />\n",
+                "  </Pages>\n",
+                "</ComicInfo>\n",
+            )
+        );
+        assert_eq!(
+            read_notes(&info.to_xml()).as_deref(),
+            Some("Missing <page 3>")
+        );
+    }
+}
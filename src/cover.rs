@@ -0,0 +1,259 @@
+use super::model::Collection;
+use super::model::Relationship;
+use super::MangadexClient;
+use super::MangadexError;
+use bytes::Bytes;
+use getset::Getters;
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Largest page size accepted by the cover endpoint.
+const MAX_COVER_LIMIT: usize = 100;
+
+/// Cover art of one or more manga, from the paginated `GET /cover`.
+#[derive(Debug, Clone, Default)]
+pub struct CoverQuery {
+    pub(crate) manga: Vec<String>,
+    pub(crate) locales: Vec<String>,
+}
+
+#[derive(Debug, Clone, Getters)]
+#[getset(get = "pub")]
+pub struct Cover {
+    id: String,
+    manga_id: String,
+    volume: Option<String>,
+    file_name: String,
+    locale: Option<String>,
+    description: String,
+}
+
+/// Covers are served as uploaded, or as 512px and 256px wide thumbnails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoverSize {
+    #[default]
+    Original,
+    Medium,
+    Small,
+}
+
+impl FromStr for CoverSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "original" => Ok(Self::Original),
+            "512" => Ok(Self::Medium),
+            "256" => Ok(Self::Small),
+            _ => Err(format!("'{s}' is not one of: original, 512, 256")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CoverData {
+    id: String,
+    attributes: CoverAttributes,
+    #[serde(default)]
+    relationships: Vec<Relationship>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CoverAttributes {
+    volume: Option<String>,
+    file_name: String,
+    locale: Option<String>,
+    #[serde(default)]
+    description: String,
+}
+
+impl CoverQuery {
+    pub fn new(manga: impl ToString) -> Self {
+        Self::default().manga(manga)
+    }
+
+    pub fn manga(mut self, manga: impl ToString) -> Self {
+        self.manga.push(manga.to_string());
+        self
+    }
+
+    pub fn locale(mut self, locale: impl ToString) -> Self {
+        self.locales.push(locale.to_string());
+        self
+    }
+
+    /// Every cover, ordered by volume.
+    pub async fn execute(self, client: &MangadexClient) -> Result<Vec<Cover>, MangadexError> {
+        let mut covers = Vec::new();
+        loop {
+            let mut query = vec![
+                ("order[volume]", "asc".to_string()),
+                ("limit", MAX_COVER_LIMIT.to_string()),
+                ("offset", covers.len().to_string()),
+            ];
+            for manga in &self.manga {
+                query.push(("manga[]", manga.clone()));
+            }
+            for locale in &self.locales {
+                query.push(("locales[]", locale.clone()));
+            }
+
+            let bytes = client
+                .get("cover")
+                .await?
+                .query(&query)
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+            let page: Collection<CoverData> = serde_json::from_slice(&bytes)?;
+            let has_more = page.has_more() && !page.data().is_empty();
+            covers.extend(page.into_data().into_iter().map(Cover::from));
+            if !has_more {
+                return Ok(covers);
+            }
+        }
+    }
+}
+
+impl Cover {
+    pub fn volume_number(&self) -> Option<f32> {
+        self.volume.as_deref()?.parse().ok()
+    }
+
+    pub fn url(&self, client: &MangadexClient, size: CoverSize) -> String {
+        let suffix = match size {
+            CoverSize::Original => "",
+            CoverSize::Medium => ".512.jpg",
+            CoverSize::Small => ".256.jpg",
+        };
+        format!(
+            "{}/covers/{}/{}{suffix}",
+            client.uploads_url(),
+            self.manga_id,
+            self.file_name
+        )
+    }
+
+    /// Extension of the file served for `size`, thumbnails are always jpeg.
+    pub fn extension(&self, size: CoverSize) -> &str {
+        match size {
+            CoverSize::Original => self
+                .file_name
+                .rsplit_once('.')
+                .map(|(_, ext)| ext)
+                .unwrap_or("jpg"),
+            CoverSize::Medium | CoverSize::Small => "jpg",
+        }
+    }
+
+    pub async fn download(
+        &self,
+        client: &MangadexClient,
+        size: CoverSize,
+    ) -> Result<Bytes, MangadexError> {
+        Ok(client
+            .get_url(self.url(client, size))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?)
+    }
+}
+
+impl From<CoverData> for Cover {
+    fn from(data: CoverData) -> Self {
+        let manga_id = data
+            .relationships
+            .iter()
+            .find(|r| r.kind() == "manga")
+            .map(|r| r.id().clone())
+            .unwrap_or_default();
+        Self {
+            id: data.id,
+            manga_id,
+            volume: data.attributes.volume,
+            file_name: data.attributes.file_name,
+            locale: data.attributes.locale,
+            description: data.attributes.description,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+
+    #[tokio::test]
+    async fn test_covers() {
+        let server = MockServer::start().await;
+        server.mount(
+            "/cover",
+            MockResponse::bytes(
+                "application/json",
+                include_str!("../tests/fixtures/covers.json"),
+            ),
+        );
+        server.mount(
+            "/covers/manga-id/b6c7ce9c.jpg.512.jpg",
+            MockResponse::bytes("image/jpeg", &b"medium cover"[..]),
+        );
+        let client = server.client();
+
+        let covers = CoverQuery::new("manga-id").execute(&client).await.unwrap();
+        assert_eq!(server.requests()[0].query_values("manga[]"), ["manga-id"]);
+        assert_eq!(covers.len(), 2);
+        assert_eq!(covers[0].volume_number(), Some(1.0));
+        assert_eq!(covers[0].manga_id(), "manga-id");
+        assert_eq!(covers[1].extension(CoverSize::Original), "png");
+        assert_eq!(covers[1].extension(CoverSize::Small), "jpg");
+        assert_eq!(
+            covers[1].url(&client, CoverSize::Small),
+            format!("{}/covers/manga-id/0f4c2d7e.png.256.jpg", server.url())
+        );
+
+        let bytes = covers[0]
+            .download(&client, CoverSize::Medium)
+            .await
+            .unwrap();
+        assert_eq!(&bytes[..], b"medium cover");
+        assert!(covers[0]
+            .download(&client, CoverSize::Original)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_covers_paginated() {
+        let server = MockServer::start().await;
+        let fixture: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/covers.json")).unwrap();
+        // One cover per page
+        server.mount_with("/cover", move |request| {
+            let offset: usize = request.query_values("offset")[0].parse().unwrap();
+            let mut page = fixture.clone();
+            let cover = page["data"][offset].take();
+            page["data"] = serde_json::json!([cover]);
+            page["limit"] = 1.into();
+            page["offset"] = offset.into();
+            MockResponse::json(page)
+        });
+
+        let covers = CoverQuery::new("manga-id")
+            .locale("ja")
+            .execute(&server.client())
+            .await
+            .unwrap();
+        let volumes: Vec<Option<f32>> = covers.iter().map(Cover::volume_number).collect();
+        assert_eq!(volumes, [Some(1.0), Some(2.0)]);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].query_values("offset"), ["1"]);
+        assert_eq!(requests[1].query_values("locales[]"), ["ja"]);
+        assert_eq!(requests[1].query_values("order[volume]"), ["asc"]);
+    }
+}
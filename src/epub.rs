@@ -0,0 +1,565 @@
+use super::comicinfo::escape;
+use super::comicinfo::unescape;
+use super::comicinfo::ComicInfo;
+use super::packager::chapter_folder_name;
+use super::packager::PackageChapter;
+use super::util::timestamp;
+use super::MangadexError;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+use zip::write::FileOptions;
+use zip::CompressionMethod;
+use zip::ZipArchive;
+use zip::ZipWriter;
+
+const CONTAINER_XML: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+    "<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n",
+    "  <rootfiles>\n",
+    "    <rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>\n",
+    "  </rootfiles>\n",
+    "</container>\n"
+);
+
+const PAGE_STYLE: &str =
+    "html, body { margin: 0; padding: 0; } img { display: block; width: 100%; height: 100%; }";
+
+/// Size given to pages whose image header cannot be read.
+const DEFAULT_PAGE_SIZE: (usize, usize) = (800, 1200);
+
+/// An image of the book, `folder` being empty for the cover.
+struct Image {
+    folder: String,
+    file_name: String,
+    bytes: Vec<u8>,
+}
+
+impl Image {
+    /// Path under `OEBPS/images`.
+    fn path(&self) -> String {
+        if self.folder.is_empty() {
+            self.file_name.clone()
+        } else {
+            format!("{}/{}", self.folder, self.file_name)
+        }
+    }
+
+    fn href(&self) -> String {
+        encode_href(&self.path())
+    }
+
+    fn media_type(&self) -> &'static str {
+        let extension = self
+            .file_name
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "png" => "image/png",
+            "gif" => "image/gif",
+            "webp" => "image/webp",
+            _ => "image/jpeg",
+        }
+    }
+
+    fn size(&self) -> (usize, usize) {
+        imagesize::blob_size(&self.bytes)
+            .map(|size| (size.width, size.height))
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+    }
+}
+
+/// Write a fixed-layout EPUB 3 with a page per image of `chapters`, `cover`
+/// first, and a table of contents entry per chapter. When `append`, chapters
+/// are added after the ones of the book already at `epub_path`, which keeps its
+/// cover, or replace the same chapter where it was.
+pub(crate) fn write_epub(
+    epub_path: &Path,
+    cover: Option<&Path>,
+    comic_info: &ComicInfo,
+    chapters: &[&PackageChapter],
+    append: bool,
+) -> Result<(), MangadexError> {
+    let mut cover_image = None;
+    let mut images = Vec::new();
+    let mut labels = Vec::new();
+    if append && epub_path.exists() {
+        (cover_image, images, labels) = read_epub(epub_path)?;
+    } else if let Some(cover) = cover {
+        let extension = cover.extension().unwrap_or_default().to_string_lossy();
+        cover_image = Some(Image {
+            folder: String::new(),
+            file_name: format!("cover.{extension}"),
+            bytes: fs::read(cover)?,
+        });
+    }
+
+    let mut next_folder = labels.len();
+    for chapter in chapters {
+        let name = chapter.folder_name();
+        // A chapter already in the book is replaced where it was
+        let previous = labels
+            .iter()
+            .position(|(folder, _)| chapter_folder_name(folder) == Some(name.as_str()));
+        let folder = match previous {
+            Some(i) => {
+                let (folder, _) = labels.remove(i);
+                images.retain(|image| image.folder != folder);
+                folder
+            }
+            None => {
+                next_folder += 1;
+                format!("{:05}_{name}", next_folder - 1)
+            }
+        };
+        let mut files: Vec<_> = fs::read_dir(&chapter.path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()?;
+        files.sort();
+        for file_path in files {
+            let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+            if !file_path.is_file() || file_name.starts_with('.') {
+                continue;
+            }
+            images.push(Image {
+                folder: folder.clone(),
+                file_name: file_name.to_string(),
+                bytes: fs::read(&file_path)?,
+            });
+        }
+        labels.push((folder, chapter.label()));
+    }
+    // Folders sort in reading order, pages keep theirs within each folder
+    images.sort_by(|a, b| a.folder.cmp(&b.folder));
+    labels.sort();
+
+    // Like cbz archives, the book is rebuilt next to the old one and swapped in
+    let tmp_path = epub_path.with_extension("epub.tmp");
+    let mut writer = ZipWriter::new(fs::File::create(&tmp_path)?);
+    // The mimetype must come first and uncompressed for readers to sniff it
+    writer.start_file(
+        "mimetype",
+        FileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    writer.write_all(b"application/epub+zip")?;
+    writer.start_file("META-INF/container.xml", FileOptions::default())?;
+    writer.write_all(CONTAINER_XML.as_bytes())?;
+
+    let title = comic_info.book_title();
+    let pages: Vec<&Image> = cover_image.iter().chain(&images).collect();
+    writer.start_file("OEBPS/content.opf", FileOptions::default())?;
+    writer.write_all(
+        package_document(comic_info, &title, cover_image.is_some(), &pages).as_bytes(),
+    )?;
+    writer.start_file("OEBPS/nav.xhtml", FileOptions::default())?;
+    writer.write_all(navigation(&title, cover_image.is_some(), &images, &labels).as_bytes())?;
+    for (i, image) in pages.iter().enumerate() {
+        writer.start_file(
+            format!("OEBPS/pages/{}", page_file_name(i)),
+            FileOptions::default(),
+        )?;
+        writer.write_all(page(&title, image).as_bytes())?;
+        writer.start_file(
+            format!("OEBPS/images/{}", image.path()),
+            FileOptions::default(),
+        )?;
+        writer.write_all(&image.bytes)?;
+    }
+    writer.finish()?;
+    fs::rename(&tmp_path, epub_path)?;
+    Ok(())
+}
+
+type Book = (Option<Image>, Vec<Image>, Vec<(String, String)>);
+
+/// Cover, chapter images and table of contents of the book at `epub_path`.
+fn read_epub(epub_path: &Path) -> Result<Book, MangadexError> {
+    let mut archive = ZipArchive::new(fs::File::open(epub_path)?)?;
+    let mut names: Vec<String> = archive
+        .file_names()
+        .filter(|name| name.starts_with("OEBPS/images/"))
+        .map(str::to_string)
+        .collect();
+    names.sort();
+
+    let mut cover = None;
+    let mut images = Vec::new();
+    for name in names {
+        let mut bytes = Vec::new();
+        archive.by_name(&name)?.read_to_end(&mut bytes)?;
+        let path = &name["OEBPS/images/".len()..];
+        match path.split_once('/') {
+            Some((folder, file_name)) => images.push(Image {
+                folder: folder.to_string(),
+                file_name: file_name.to_string(),
+                bytes,
+            }),
+            None => {
+                cover = Some(Image {
+                    folder: String::new(),
+                    file_name: path.to_string(),
+                    bytes,
+                })
+            }
+        }
+    }
+
+    let mut nav = String::new();
+    archive
+        .by_name("OEBPS/nav.xhtml")?
+        .read_to_string(&mut nav)?;
+    let mut folders: Vec<&str> = images.iter().map(|i| i.folder.as_str()).collect();
+    folders.dedup();
+    let toc = nav_labels(&nav);
+    let labels = folders
+        .iter()
+        .enumerate()
+        .map(|(i, folder)| {
+            let label = toc.get(i).cloned().unwrap_or_else(|| folder.to_string());
+            (folder.to_string(), label)
+        })
+        .collect();
+    Ok((cover, images, labels))
+}
+
+/// Labels of the table of contents of a nav document written by
+/// [`navigation`].
+fn nav_labels(nav: &str) -> Vec<String> {
+    let Some(start) = nav.find("<nav epub:type=\"toc\"") else {
+        return Vec::new();
+    };
+    let toc = &nav[start..];
+    let toc = &toc[..toc.find("</nav>").unwrap_or(toc.len())];
+    toc.lines()
+        .filter_map(|line| {
+            let line = line.trim().strip_prefix("<li><a href=\"")?;
+            let (_, rest) = line.split_once("\">")?;
+            let (label, _) = rest.split_once("</a>")?;
+            Some(unescape(label))
+        })
+        .collect()
+}
+
+fn page_file_name(index: usize) -> String {
+    format!("page_{index:05}.xhtml")
+}
+
+fn package_document(
+    comic_info: &ComicInfo,
+    title: &str,
+    has_cover: bool,
+    pages: &[&Image],
+) -> String {
+    let language = comic_info.language.as_deref().unwrap_or("und");
+    let mut identifier = comic_info
+        .web
+        .clone()
+        .unwrap_or_else(|| comic_info.series.clone());
+    if let Some(number) = &comic_info.number {
+        let _ = write!(identifier, "#chapter-{number}");
+    } else if let Some(volume) = &comic_info.volume {
+        let _ = write!(identifier, "#volume-{volume}");
+    }
+
+    let mut opf = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    let _ = writeln!(
+        opf,
+        "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\" xml:lang=\"{}\" prefix=\"rendition: http://www.idpf.org/vocab/rendition/#\">",
+        escape(language)
+    );
+    opf.push_str("  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n");
+    let _ = writeln!(
+        opf,
+        "    <dc:identifier id=\"book-id\">{}</dc:identifier>",
+        escape(&identifier)
+    );
+    let _ = writeln!(opf, "    <dc:title>{}</dc:title>", escape(title));
+    let _ = writeln!(opf, "    <dc:language>{}</dc:language>", escape(language));
+    let mut creators: Vec<&String> = comic_info.writer.iter().collect();
+    for artist in &comic_info.penciller {
+        if !creators.contains(&artist) {
+            creators.push(artist);
+        }
+    }
+    for creator in creators {
+        let _ = writeln!(opf, "    <dc:creator>{}</dc:creator>", escape(creator));
+    }
+    if let Some(summary) = &comic_info.summary {
+        let _ = writeln!(
+            opf,
+            "    <dc:description>{}</dc:description>",
+            escape(summary)
+        );
+    }
+    for genre in &comic_info.genre {
+        let _ = writeln!(opf, "    <dc:subject>{}</dc:subject>", escape(genre));
+    }
+    if let Some(year) = comic_info.year {
+        let _ = writeln!(opf, "    <dc:date>{year}</dc:date>");
+    }
+    if let Some(web) = &comic_info.web {
+        let _ = writeln!(opf, "    <dc:source>{}</dc:source>", escape(web));
+    }
+    let _ = writeln!(
+        opf,
+        "    <meta property=\"belongs-to-collection\" id=\"series\">{}</meta>",
+        escape(&comic_info.series)
+    );
+    opf.push_str("    <meta refines=\"#series\" property=\"collection-type\">series</meta>\n");
+    if let Some(volume) = &comic_info.volume {
+        let _ = writeln!(
+            opf,
+            "    <meta refines=\"#series\" property=\"group-position\">{}</meta>",
+            escape(volume)
+        );
+    }
+    let _ = writeln!(
+        opf,
+        "    <meta property=\"dcterms:modified\">{}</meta>",
+        timestamp(SystemTime::now())
+    );
+    opf.push_str(concat!(
+        "    <meta property=\"rendition:layout\">pre-paginated</meta>\n",
+        "    <meta property=\"rendition:orientation\">portrait</meta>\n",
+        "    <meta property=\"rendition:spread\">none</meta>\n",
+    ));
+    // EPUB 2 readers find the cover through this, it must name a cover image
+    if has_cover {
+        opf.push_str("    <meta name=\"cover\" content=\"image-00000\"/>\n");
+    }
+    opf.push_str(concat!(
+        "  </metadata>\n",
+        "  <manifest>\n",
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n"
+    ));
+    for (i, image) in pages.iter().enumerate() {
+        // Readers show the cover image in their library, the first page when
+        // the book has no cover of its own
+        let properties = if i == 0 {
+            " properties=\"cover-image\""
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            opf,
+            "    <item id=\"image-{i:05}\" href=\"images/{}\" media-type=\"{}\"{properties}/>",
+            image.href(),
+            image.media_type()
+        );
+        let _ = writeln!(
+            opf,
+            "    <item id=\"page-{i:05}\" href=\"pages/{}\" media-type=\"application/xhtml+xml\"/>",
+            page_file_name(i)
+        );
+    }
+    opf.push_str("  </manifest>\n");
+    let direction = if comic_info.right_to_left {
+        "rtl"
+    } else {
+        "ltr"
+    };
+    let _ = writeln!(opf, "  <spine page-progression-direction=\"{direction}\">");
+    for i in 0..pages.len() {
+        let properties = if i == 0 && has_cover {
+            " properties=\"rendition:page-spread-center\""
+        } else {
+            ""
+        };
+        let _ = writeln!(opf, "    <itemref idref=\"page-{i:05}\"{properties}/>");
+    }
+    opf.push_str("  </spine>\n</package>\n");
+    opf
+}
+
+fn navigation(
+    title: &str,
+    has_cover: bool,
+    images: &[Image],
+    labels: &[(String, String)],
+) -> String {
+    let mut nav = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<!DOCTYPE html>\n",
+        "<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n",
+        "<head>\n"
+    ));
+    let _ = writeln!(nav, "  <title>{}</title>", escape(title));
+    nav.push_str("</head>\n<body>\n  <nav epub:type=\"toc\" id=\"toc\">\n    <ol>\n");
+    let offset = usize::from(has_cover);
+    for (folder, label) in labels {
+        let Some(first) = images.iter().position(|image| &image.folder == folder) else {
+            continue;
+        };
+        let _ = writeln!(
+            nav,
+            "      <li><a href=\"pages/{}\">{}</a></li>",
+            page_file_name(first + offset),
+            escape(label)
+        );
+    }
+    nav.push_str("    </ol>\n  </nav>\n  <nav epub:type=\"landmarks\" hidden=\"\">\n    <ol>\n");
+    if has_cover {
+        let _ = writeln!(
+            nav,
+            "      <li><a epub:type=\"cover\" href=\"pages/{}\">Cover</a></li>",
+            page_file_name(0)
+        );
+    }
+    if !images.is_empty() {
+        let _ = writeln!(
+            nav,
+            "      <li><a epub:type=\"bodymatter\" href=\"pages/{}\">Start</a></li>",
+            page_file_name(offset)
+        );
+    }
+    nav.push_str("    </ol>\n  </nav>\n</body>\n</html>\n");
+    nav
+}
+
+fn page(title: &str, image: &Image) -> String {
+    let (width, height) = image.size();
+    let mut page = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<!DOCTYPE html>\n",
+        "<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n",
+        "<head>\n"
+    ));
+    let _ = writeln!(page, "  <title>{}</title>", escape(title));
+    let _ = writeln!(
+        page,
+        "  <meta name=\"viewport\" content=\"width={width}, height={height}\"/>"
+    );
+    let _ = writeln!(page, "  <style>{PAGE_STYLE}</style>");
+    let _ = writeln!(
+        page,
+        "</head>\n<body>\n  <img src=\"../images/{}\" alt=\"\"/>\n</body>\n</html>",
+        image.href()
+    );
+    page
+}
+
+/// Percent-encode what is not allowed in a relative URL path.
+fn encode_href(path: &str) -> String {
+    let mut href = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                href.push(byte as char)
+            }
+            byte => {
+                let _ = write!(href, "%{byte:02X}");
+            }
+        }
+    }
+    href
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::manifest::INCOMPLETE_MARKER_FILE_NAME;
+    use crate::packager::archived_chapters;
+    use crate::packager::test::chapter_with;
+    use crate::packager::PackageFormat;
+    use crate::packager::PackageMode;
+    use crate::packager::Packager;
+    use std::collections::HashSet;
+
+    fn read(archive: &mut ZipArchive<fs::File>, name: &str) -> String {
+        let mut content = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn test_write_epub() {
+        let dir = tempfile::tempdir().unwrap();
+        // 2x3 png header, enough for the page size
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend([0, 0, 0, 2, 0, 0, 0, 3, 8, 2, 0, 0, 0]);
+        fs::write(dir.path().join("cover.png"), &png).unwrap();
+        let chapter = |name| {
+            chapter_with(
+                dir.path(),
+                name,
+                &[("page_0.jpg", b"page 0"), ("page_1.png", &png)],
+            )
+        };
+        let packager = Packager::new(PackageMode::PerVolume, "Love & Coffee")
+            .format(PackageFormat::Epub)
+            .comic_info(ComicInfo::new("Love & Coffee").language("en"))
+            .cover(Some(1), dir.path().join("cover.png"));
+
+        let first = [PackageChapter::new(chapter("chapter_1"))
+            .volume(1)
+            .chapter(1)
+            .title("Mocha")];
+        let written = packager.package(dir.path(), &first).unwrap();
+        assert_eq!(written, [dir.path().join("Love & Coffee - Volume 1.epub")]);
+        let second = [PackageChapter::new(chapter("chapter_2"))
+            .volume(1)
+            .chapter(2)];
+        assert!(packager.is_packaged(dir.path(), &first[0]));
+        assert!(!packager.is_packaged(dir.path(), &second[0]));
+        // Pages of the second chapter failed to download, its folder stays to
+        // resume them
+        fs::write(
+            second[0].path().join(INCOMPLETE_MARKER_FILE_NAME),
+            "page 2 (a.jpg): not found\n",
+        )
+        .unwrap();
+        packager.package(dir.path(), &second).unwrap();
+        assert!(!first[0].path().exists());
+        assert!(second[0].path().exists());
+
+        let mut archive = ZipArchive::new(fs::File::open(&written[0]).unwrap()).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        assert_eq!(read(&mut archive, "mimetype"), "application/epub+zip");
+        let opf = read(&mut archive, "OEBPS/content.opf");
+        assert!(opf.contains("<dc:title>Love &amp; Coffee - Volume 1</dc:title>"));
+        assert!(opf.contains("<dc:language>en</dc:language>"));
+        assert!(opf.contains("<meta property=\"rendition:layout\">pre-paginated</meta>"));
+        assert!(opf.contains("<meta name=\"cover\" content=\"image-00000\"/>"));
+        assert!(opf.contains(
+            "<item id=\"image-00000\" href=\"images/cover.png\" media-type=\"image/png\" properties=\"cover-image\"/>"
+        ));
+        assert!(opf.contains(
+            "<item id=\"image-00003\" href=\"images/00001_chapter_2/page_0.jpg\" media-type=\"image/jpeg\"/>"
+        ));
+        assert!(opf.contains("<itemref idref=\"page-00004\"/>\n  </spine>"));
+        let nav = read(&mut archive, "OEBPS/nav.xhtml");
+        assert_eq!(
+            nav_labels(&nav),
+            ["Chapter 1: Mocha".to_string(), "Chapter 2".to_string()]
+        );
+        assert!(nav.contains("<li><a href=\"pages/page_00003.xhtml\">Chapter 2</a></li>"));
+        let page = read(&mut archive, "OEBPS/pages/page_00002.xhtml");
+        assert!(page.contains("content=\"width=2, height=3\""));
+        assert!(page.contains("src=\"../images/00000_chapter_1/page_1.png\""));
+        assert_eq!(
+            archived_chapters(&written[0]).unwrap(),
+            HashSet::from(["chapter_1".to_string(), "chapter_2".to_string()])
+        );
+
+        // Resumed, the second chapter is packaged again in its own place
+        fs::remove_file(second[0].path().join(INCOMPLETE_MARKER_FILE_NAME)).unwrap();
+        packager.package(dir.path(), &second).unwrap();
+        assert!(!second[0].path().exists());
+        let mut archive = ZipArchive::new(fs::File::open(&written[0]).unwrap()).unwrap();
+        let opf = read(&mut archive, "OEBPS/content.opf");
+        assert_eq!(opf.matches("href=\"images/00001_chapter_2/").count(), 2);
+        assert!(!opf.contains("00002_"));
+        assert_eq!(
+            nav_labels(&read(&mut archive, "OEBPS/nav.xhtml")),
+            ["Chapter 1: Mocha".to_string(), "Chapter 2".to_string()]
+        );
+    }
+}
@@ -0,0 +1,464 @@
+use super::model::Collection;
+use super::model::Relationship;
+use super::util::civil_time;
+use super::util::days_from_civil;
+use super::MangadexClient;
+use super::MangadexError;
+use getset::Getters;
+use serde::Deserialize;
+
+/// Largest page size accepted by the feed endpoint.
+pub const MAX_FEED_LIMIT: usize = 500;
+
+/// Every chapter of a manga, from the paginated `GET /manga/{id}/feed`.
+#[derive(Debug, Clone)]
+pub struct MangaFeed {
+    pub(crate) path: String,
+    pub(crate) since: Option<String>,
+    pub(crate) groups: Vec<String>,
+    pub(crate) translated_language: Vec<String>,
+    pub(crate) include_external: bool,
+    pub(crate) limit: usize,
+}
+
+#[derive(Debug, Clone, Getters)]
+#[getset(get = "pub")]
+pub struct ChapterInfo {
+    id: String,
+    volume: Option<String>,
+    chapter: Option<String>,
+    title: Option<String>,
+    language: String,
+    pages: usize,
+    publish_at: String,
+    /// Set for chapters hosted outside mangadex, which have no pages to download.
+    external_url: Option<String>,
+    groups: Vec<ScanlationGroup>,
+    uploader: Option<String>,
+    manga: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct ScanlationGroup {
+    id: String,
+    name: String,
+    /// Whether the group is an official publisher.
+    official: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChapterData {
+    id: String,
+    attributes: ChapterAttributes,
+    #[serde(default)]
+    relationships: Vec<Relationship>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChapterAttributes {
+    volume: Option<String>,
+    chapter: Option<String>,
+    title: Option<String>,
+    translated_language: String,
+    #[serde(default)]
+    pages: usize,
+    publish_at: String,
+    external_url: Option<String>,
+}
+
+impl MangaFeed {
+    pub fn new(id: impl ToString) -> Self {
+        Self {
+            path: format!("manga/{}/feed", id.to_string()),
+            since: None,
+            groups: Vec::new(),
+            translated_language: Vec::new(),
+            include_external: true,
+            limit: MAX_FEED_LIMIT,
+        }
+    }
+
+    /// Only keep chapters from this scanlation group. The feed endpoint has no
+    /// such filter, so it is applied once the chapters are fetched.
+    pub fn group(mut self, group: impl ToString) -> Self {
+        self.groups.push(group.to_string());
+        self
+    }
+
+    pub fn language(mut self, language: impl ToString) -> Self {
+        self.translated_language.push(language.to_string());
+        self
+    }
+
+    pub fn include_external(mut self, include_external: bool) -> Self {
+        self.include_external = include_external;
+        self
+    }
+
+    /// Chapters of every manga followed by the logged in user, from
+    /// `GET /user/follows/manga/feed`, oldest first.
+    pub fn follows() -> Self {
+        Self {
+            path: String::from("user/follows/manga/feed"),
+            ..Self::new("")
+        }
+    }
+
+    /// Only keep chapters published at or after `timestamp`, a date or a date
+    /// and time in UTC such as `2023-05-01T12:00:00`, or with its UTC offset as
+    /// in `2023-05-01T21:00:00+09:00`.
+    pub fn since(mut self, timestamp: &str) -> Self {
+        self.since = Some(api_timestamp(timestamp));
+        self
+    }
+
+    /// Number of chapters requested per page, at most [`MAX_FEED_LIMIT`].
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit.clamp(1, MAX_FEED_LIMIT);
+        self
+    }
+
+    /// Fetch pages until the feed is exhausted, ordered by volume then chapter.
+    pub async fn execute(self, client: &MangadexClient) -> Result<Vec<ChapterInfo>, MangadexError> {
+        let mut chapters = Vec::new();
+        let mut offset = 0;
+        loop {
+            let page = self.page(client, offset).await?;
+            offset += page.data().len();
+            let has_more = page.has_more() && !page.data().is_empty();
+            chapters.extend(page.into_data().into_iter().map(ChapterInfo::from));
+            if !has_more {
+                break;
+            }
+        }
+        if !self.groups.is_empty() {
+            chapters.retain(|c| c.groups.iter().any(|g| self.groups.contains(&g.id)));
+        }
+        Ok(chapters)
+    }
+
+    async fn page(
+        &self,
+        client: &MangadexClient,
+        offset: usize,
+    ) -> Result<Collection<ChapterData>, MangadexError> {
+        let mut query = vec![
+            ("includes[]", "scanlation_group".to_string()),
+            ("includes[]", "user".to_string()),
+        ];
+        // Chapters published while paging through the follows come last
+        // instead of shifting the pages already fetched
+        if self.since.is_some() || self.path.starts_with("user/") {
+            query.push(("order[publishAt]", "asc".to_string()));
+        } else {
+            query.push(("order[volume]", "asc".to_string()));
+            query.push(("order[chapter]", "asc".to_string()));
+        }
+        query.extend([
+            (
+                "includeExternalUrl",
+                u8::from(self.include_external).to_string(),
+            ),
+            ("limit", self.limit.to_string()),
+            ("offset", offset.to_string()),
+        ]);
+        if let Some(since) = &self.since {
+            query.push(("publishAtSince", since.clone()));
+        }
+        for language in &self.translated_language {
+            query.push(("translatedLanguage[]", language.clone()));
+        }
+
+        let bytes = client
+            .get(&self.path)
+            .await?
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+impl ChapterInfo {
+    pub fn volume_number(&self) -> Option<f32> {
+        self.volume.as_deref()?.parse().ok()
+    }
+
+    pub fn chapter_number(&self) -> Option<f32> {
+        self.chapter.as_deref()?.parse().ok()
+    }
+
+    pub fn is_external(&self) -> bool {
+        self.external_url.is_some()
+    }
+
+    pub fn is_official(&self) -> bool {
+        self.groups.iter().any(|g| g.official)
+    }
+
+    /// When the chapter was published, in the format of [`MangaFeed::since`].
+    pub fn published(&self) -> String {
+        api_timestamp(&self.publish_at)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn new(id: &str, group: &str, publish_at: &str, pages: usize) -> Self {
+        Self {
+            id: id.to_string(),
+            volume: None,
+            chapter: None,
+            title: None,
+            language: "en".to_string(),
+            pages,
+            publish_at: publish_at.to_string(),
+            external_url: None,
+            groups: vec![ScanlationGroup {
+                id: group.to_lowercase(),
+                name: group.to_string(),
+                official: false,
+            }],
+            uploader: Some(format!("uploader of {id}")),
+            manga: None,
+        }
+    }
+}
+
+impl From<ChapterData> for ChapterInfo {
+    fn from(data: ChapterData) -> Self {
+        let groups = data
+            .relationships
+            .iter()
+            .filter(|r| r.kind() == "scanlation_group")
+            .map(|r| ScanlationGroup {
+                id: r.id().clone(),
+                name: r.attribute("name").unwrap_or_default().to_string(),
+                official: r
+                    .attributes()
+                    .as_ref()
+                    .and_then(|a| a.get("official"))
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or_default(),
+            })
+            .collect();
+        let uploader = data
+            .relationships
+            .iter()
+            .find(|r| r.kind() == "user")
+            .and_then(|r| r.attribute("username"))
+            .map(str::to_string);
+        let manga = data
+            .relationships
+            .iter()
+            .find(|r| r.kind() == "manga")
+            .map(|r| r.id().clone());
+        let attributes = data.attributes;
+        Self {
+            id: data.id,
+            volume: attributes.volume,
+            chapter: attributes.chapter,
+            title: attributes.title.filter(|t| !t.is_empty()),
+            language: attributes.translated_language,
+            pages: attributes.pages,
+            publish_at: attributes.publish_at,
+            external_url: attributes.external_url,
+            groups,
+            uploader,
+            manga,
+        }
+    }
+}
+
+/// `timestamp` as accepted by the `...Since` parameters of the API, which
+/// take neither fractions of seconds nor a time zone. A UTC offset such as
+/// `+09:00` is applied to get the time in UTC.
+pub(crate) fn api_timestamp(timestamp: &str) -> String {
+    let local: String = timestamp.chars().take(19).collect();
+    if local.len() == 10 {
+        return format!("{local}T00:00:00");
+    }
+    let offset = timestamp
+        .get(19..)
+        .map(|zone| zone.trim_start_matches(|c: char| c == '.' || c.is_ascii_digit()))
+        .and_then(utc_offset);
+    match offset {
+        Some(offset) if offset != 0 => to_utc(&local, offset).unwrap_or(local),
+        _ => local,
+    }
+}
+
+/// Seconds ahead of UTC of a `+09:00`, `-0530` or `Z` zone.
+fn utc_offset(zone: &str) -> Option<i64> {
+    let sign = match zone.chars().next()? {
+        'Z' if zone.len() == 1 => return Some(0),
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = zone[1..].replacen(':', "", 1);
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// `2023-05-01T12:00:00`, `offset` seconds ahead of UTC, in UTC.
+fn to_utc(local: &str, offset: i64) -> Option<String> {
+    let field = |range: std::ops::Range<usize>| local.get(range)?.parse::<i64>().ok();
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    let secs =
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    let (year, month, day, hour, minute, second) = civil_time(secs);
+    Some(format!(
+        "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}"
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use crate::query::{GetChapters, Volume};
+
+    fn feed_page(offset: usize, total: usize, chapters: &[serde_json::Value]) -> MockResponse {
+        MockResponse::json(serde_json::json!({
+            "result": "ok",
+            "response": "collection",
+            "data": chapters,
+            "limit": 2,
+            "offset": offset,
+            "total": total,
+        }))
+    }
+
+    fn chapter(id: &str, volume: Option<&str>, chapter: &str, group: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "type": "chapter",
+            "attributes": {
+                "volume": volume,
+                "chapter": chapter,
+                "title": "",
+                "translatedLanguage": "en",
+                "externalUrl": null,
+                "publishAt": "2023-05-01T12:00:00+00:00",
+                "pages": 20,
+                "version": 1
+            },
+            "relationships": [
+                {"id": group, "type": "scanlation_group", "attributes": {"name": format!("group {group}")}},
+                {"id": "manga", "type": "manga"},
+                {"id": "user", "type": "user", "attributes": {"username": "uploader"}}
+            ]
+        })
+    }
+
+    #[test]
+    fn test_api_timestamp() {
+        assert_eq!(api_timestamp("2023-05-01"), "2023-05-01T00:00:00");
+        assert_eq!(api_timestamp("2023-05-01T12:00:00"), "2023-05-01T12:00:00");
+        assert_eq!(
+            api_timestamp("2023-04-10T09:12:31+00:00"),
+            "2023-04-10T09:12:31"
+        );
+        assert_eq!(api_timestamp("2023-04-10T09:12:31Z"), "2023-04-10T09:12:31");
+        assert_eq!(
+            api_timestamp("2023-05-01T12:00:00+09:00"),
+            "2023-05-01T03:00:00"
+        );
+        assert_eq!(
+            api_timestamp("2023-05-01T03:00:00.250+09:00"),
+            "2023-04-30T18:00:00"
+        );
+        assert_eq!(
+            api_timestamp("2024-02-28T20:30:00-0530"),
+            "2024-02-29T02:00:00"
+        );
+        assert_eq!(
+            api_timestamp("2023-12-31T23:00:00-02:00"),
+            "2024-01-01T01:00:00"
+        );
+        assert_eq!(
+            api_timestamp("1965-06-01T03:00:00+09:00"),
+            "1965-05-31T18:00:00"
+        );
+        assert_eq!(
+            api_timestamp("1969-12-31T20:00:00-05:00"),
+            "1970-01-01T01:00:00"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_manga_feed() {
+        let server = MockServer::start().await;
+        server.mount_with("/manga/manga-id/feed", |req| {
+            match req.query_values("offset")[0].as_str() {
+                "0" => MockResponse::bytes(
+                    "application/json",
+                    include_str!("../tests/fixtures/feed.json"),
+                ),
+                "2" => feed_page(
+                    2,
+                    4,
+                    &[
+                        chapter("c3", Some("1"), "2", "g1"),
+                        chapter("c4", Some("1"), "2", "g2"),
+                    ],
+                ),
+                _ => feed_page(4, 4, &[]),
+            }
+        });
+        let client = server.client();
+
+        let chapters = MangaFeed::new("manga-id")
+            .language("en")
+            .limit(2)
+            .execute(&client)
+            .await
+            .unwrap();
+        assert_eq!(server.hits("/manga/manga-id/feed"), 2);
+        let request = &server.requests()[0];
+        assert_eq!(request.query_values("translatedLanguage[]"), ["en"]);
+        assert_eq!(
+            request.query_values("includes[]"),
+            ["scanlation_group", "user"]
+        );
+
+        let ids: Vec<&str> = chapters.iter().map(|c| c.id().as_str()).collect();
+        assert_eq!(ids, ["c1", "c2", "c3", "c4"]);
+        let first = &chapters[0];
+        assert_eq!(first.title().as_deref(), Some("The Café"));
+        assert_eq!(first.volume_number(), Some(1.0));
+        assert_eq!(first.chapter_number(), Some(1.0));
+        assert_eq!(*first.pages(), 24);
+        assert_eq!(first.publish_at(), "2023-04-10T09:12:31+00:00");
+        assert_eq!(first.groups()[0].name(), "Café Scans");
+        assert_eq!(first.uploader().as_deref(), Some("hayato"));
+        assert!(chapters[1].is_external());
+        assert_eq!(chapters[1].pages(), &0);
+        assert!(chapters[1].title().is_none());
+
+        let volumes = Volume::from_feed(&chapters);
+        assert_eq!(volumes.len(), 1);
+        let selected = (&volumes).get_chapters();
+        assert_eq!(selected.len(), 2);
+        assert_eq!(selected[0].id(), "c1");
+        assert_eq!(selected[1].others(), &["c4"]);
+
+        let chapters = MangaFeed::new("manga-id")
+            .limit(2)
+            .group("g2")
+            .execute(&client)
+            .await
+            .unwrap();
+        let ids: Vec<&str> = chapters.iter().map(|c| c.id().as_str()).collect();
+        assert_eq!(ids, ["c4"]);
+    }
+}
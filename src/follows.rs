@@ -0,0 +1,187 @@
+use super::feed::api_timestamp;
+use super::feed::ChapterInfo;
+use super::feed::MangaFeed;
+use super::MangadexError;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+pub const FOLLOW_STATE_FILE_NAME: &str = ".mgdm-follows.json";
+
+/// How far syncing the follows went, so the next sync only fetches chapters
+/// published since.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct FollowState {
+    /// Publication time of the latest chapter handled
+    since: Option<String>,
+    /// Chapters handled that were published at `since`, which the next feed
+    /// returns again
+    seen: Vec<String>,
+}
+
+impl FollowState {
+    /// State saved at `path`, `None` when nothing was synced yet.
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>, MangadexError> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), MangadexError> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Start the next sync at `timestamp` instead, see [`MangaFeed::since`].
+    pub fn starting_at(timestamp: &str) -> Self {
+        Self {
+            since: Some(api_timestamp(timestamp)),
+            seen: Vec::new(),
+        }
+    }
+
+    /// Feed of the followed manga chapters not synced yet.
+    pub fn feed(&self) -> MangaFeed {
+        match &self.since {
+            Some(since) => MangaFeed::follows().since(since),
+            None => MangaFeed::follows(),
+        }
+    }
+
+    /// Leave out the chapters of `chapters` handled by the last sync.
+    pub fn pending(&self, mut chapters: Vec<ChapterInfo>) -> Vec<ChapterInfo> {
+        chapters.retain(|c| !self.seen.contains(c.id()));
+        chapters
+    }
+
+    /// Move past `chapters`, up to the first one of `failed` so that it is
+    /// fetched again by the next sync.
+    pub fn advance(&mut self, chapters: &[ChapterInfo], failed: &[String]) {
+        let mut chapters: Vec<(String, &ChapterInfo)> =
+            chapters.iter().map(|c| (c.published(), c)).collect();
+        chapters.sort_by(|a, b| a.0.cmp(&b.0));
+        for (published, chapter) in chapters {
+            if failed.contains(chapter.id()) {
+                break;
+            }
+            if self.since.as_ref() != Some(&published) {
+                self.since = Some(published);
+                self.seen.clear();
+            }
+            self.seen.push(chapter.id().clone());
+        }
+    }
+}
+
+/// `chapters` grouped by manga, in the order each manga first appears.
+pub fn chapters_by_manga(chapters: Vec<ChapterInfo>) -> Vec<(String, Vec<ChapterInfo>)> {
+    let mut grouped: Vec<(String, Vec<ChapterInfo>)> = Vec::new();
+    for chapter in chapters {
+        let Some(manga) = chapter.manga().clone() else {
+            continue;
+        };
+        match grouped.iter_mut().find(|(id, _)| *id == manga) {
+            Some((_, chapters)) => chapters.push(chapter),
+            None => grouped.push((manga, vec![chapter])),
+        }
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use serde_json::json;
+
+    fn chapter(id: &str, manga: &str, publish_at: &str) -> serde_json::Value {
+        json!({
+            "id": id,
+            "type": "chapter",
+            "attributes": {
+                "volume": null,
+                "chapter": "1",
+                "title": null,
+                "translatedLanguage": "en",
+                "externalUrl": null,
+                "publishAt": publish_at,
+                "pages": 20
+            },
+            "relationships": [
+                {"id": "g1", "type": "scanlation_group", "attributes": {"name": "Café Scans"}},
+                {"id": manga, "type": "manga"}
+            ]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_follow_sync() {
+        let server = MockServer::start().await;
+        server.mount(
+            "/user/follows/manga/feed",
+            MockResponse::json(json!({
+                "result": "ok",
+                "response": "collection",
+                "data": [
+                    chapter("c1", "m1", "2023-05-01T12:00:00+00:00"),
+                    chapter("c2", "m2", "2023-05-02T12:00:00+00:00"),
+                    chapter("c3", "m1", "2023-05-03T12:00:00+00:00"),
+                    chapter("c4", "m2", "2023-05-03T12:00:00+00:00"),
+                    chapter("c5", "m2", "2023-05-04T12:00:00+00:00"),
+                ],
+                "limit": 500,
+                "offset": 0,
+                "total": 5,
+            })),
+        );
+        let client = server.client();
+
+        let mut state = FollowState::starting_at("2023-05-01");
+        let chapters = state.feed().language("en").execute(&client).await.unwrap();
+        let request = &server.requests()[0];
+        assert_eq!(
+            request.query_values("publishAtSince"),
+            ["2023-05-01T00:00:00"]
+        );
+        assert_eq!(request.query_values("order[publishAt]"), ["asc"]);
+
+        let grouped: Vec<(String, Vec<String>)> = chapters_by_manga(chapters.clone())
+            .into_iter()
+            .map(|(manga, c)| (manga, c.iter().map(|c| c.id().clone()).collect()))
+            .collect();
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped[0].0, "m1");
+        assert_eq!(grouped[0].1, ["c1", "c3"]);
+        assert_eq!(grouped[1].0, "m2");
+        assert_eq!(grouped[1].1, ["c2", "c4", "c5"]);
+
+        state.advance(&chapters, &[String::from("c5")]);
+        assert_eq!(state.since().as_deref(), Some("2023-05-03T12:00:00"));
+        assert_eq!(state.seen(), &["c3", "c4"]);
+
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join(FOLLOW_STATE_FILE_NAME);
+        assert!(FollowState::load(&path).unwrap().is_none());
+        state.save(&path).unwrap();
+        let state = FollowState::load(&path).unwrap().unwrap();
+        state.feed().execute(&client).await.unwrap();
+        assert_eq!(
+            server.requests()[1].query_values("publishAtSince"),
+            ["2023-05-03T12:00:00"]
+        );
+        let pending: Vec<String> = state
+            .pending(chapters)
+            .iter()
+            .map(|c| c.id().clone())
+            .collect();
+        assert_eq!(pending, ["c1", "c2", "c5"]);
+    }
+}
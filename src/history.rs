@@ -0,0 +1,255 @@
+use super::manifest::ChapterManifest;
+use super::service::ChapterDownloadReport;
+use super::util::timestamp;
+use super::MangadexError;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tracing::warn;
+
+pub const HISTORY_FILE_NAME: &str = ".mgdm-history.jsonl";
+
+/// A chapter downloaded, as recorded in the [`History`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub manga: String,
+    /// Series title at the time of the download
+    pub title: String,
+    pub chapter_id: String,
+    pub chapter: Option<String>,
+    pub volume: Option<String>,
+    /// Names of the scanlation groups of the release
+    pub groups: Vec<String>,
+    pub language: String,
+    pub pages: usize,
+    /// Hex SHA-256 of every page in reading order, `None` for the pages that
+    /// failed to download, the chapter having been kept without them
+    pub checksums: Vec<Option<String>>,
+    /// Folder of the pages
+    pub path: PathBuf,
+    pub archive: Option<PathBuf>,
+    pub downloaded_at: String,
+    pub packaged_at: Option<String>,
+}
+
+/// Journal of the chapters downloaded, kept as one JSON object per line so
+/// that recording a chapter is a single append.
+///
+/// A chapter recorded again supersedes its previous lines.
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+    entries: Vec<HistoryEntry>,
+    /// The journal ends with a partial line, the next one must not be glued
+    /// to it
+    torn: bool,
+}
+
+impl HistoryEntry {
+    pub fn new(manga: impl ToString, chapter_id: impl ToString) -> Self {
+        Self {
+            manga: manga.to_string(),
+            chapter_id: chapter_id.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Fill in the pages of `report`.
+    pub fn downloaded(mut self, report: &ChapterDownloadReport) -> Self {
+        self.pages = report.total_pages();
+        self.checksums = vec![None; report.total_pages()];
+        for page in report.pages() {
+            if let Some(checksum) = self.checksums.get_mut(*page.index()) {
+                *checksum = Some(page.sha256().clone());
+            }
+        }
+        self.path = report.path().clone();
+        self.downloaded_at = timestamp(SystemTime::now());
+        self
+    }
+
+    pub fn packaged(mut self, archive: impl AsRef<Path>) -> Self {
+        self.archive = Some(archive.as_ref().to_path_buf());
+        self.packaged_at = Some(timestamp(SystemTime::now()));
+        self
+    }
+
+    /// Whether every page of the chapter was downloaded.
+    pub fn is_complete(&self) -> bool {
+        self.checksums.iter().all(Option::is_some)
+    }
+
+    /// Indexes of the pages that failed to download.
+    pub fn missing_pages(&self) -> Vec<usize> {
+        self.checksums
+            .iter()
+            .enumerate()
+            .filter(|(_, checksum)| checksum.is_none())
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Whether the chapter is still around, in its archive or its folder.
+    pub fn is_present(&self) -> bool {
+        match &self.archive {
+            Some(archive) => archive.exists(),
+            None => ChapterManifest::is_complete(&self.path),
+        }
+    }
+}
+
+impl History {
+    /// Journal at `path`, empty when it does not exist yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MangadexError> {
+        let path = path.as_ref();
+        let mut history = Self {
+            path: path.to_path_buf(),
+            entries: Vec::new(),
+            torn: false,
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(history),
+            Err(e) => return Err(e.into()),
+        };
+        history.torn = !text.is_empty() && !text.ends_with('\n');
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            // The last line is cut short when interrupted while appending
+            match serde_json::from_str(line) {
+                Ok(entry) => history.insert(entry),
+                Err(e) => warn!("Skip line {} of {}: {e}", i + 1, path.display()),
+            }
+        }
+        Ok(history)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Latest entry of every chapter, in the order they were first recorded.
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, chapter_id: &str) -> Option<&HistoryEntry> {
+        self.entries.iter().find(|e| e.chapter_id == chapter_id)
+    }
+
+    /// Chapter `chapter` of `manga` in `language`, from whichever release was
+    /// downloaded. Releases still around come first.
+    pub fn find(
+        &self,
+        manga: &str,
+        chapter: Option<&str>,
+        language: &str,
+    ) -> Option<&HistoryEntry> {
+        let mut matches = self.entries.iter().filter(|e| {
+            e.manga == manga && e.chapter.as_deref() == chapter && e.language == language
+        });
+        let first = matches.clone().next();
+        matches.find(|e| e.is_present()).or(first)
+    }
+
+    /// Append `entry` to the journal.
+    pub fn record(&mut self, entry: HistoryEntry) -> Result<(), MangadexError> {
+        if let Some(dir) = self.path.parent().filter(|x| !x.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let mut line = if self.torn { vec![b'\n'] } else { Vec::new() };
+        serde_json::to_writer(&mut line, &entry)?;
+        line.push(b'\n');
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        self.torn = false;
+        self.insert(entry);
+        Ok(())
+    }
+
+    fn insert(&mut self, entry: HistoryEntry) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|e| e.chapter_id == entry.chapter_id)
+        {
+            Some(previous) => *previous = entry,
+            None => self.entries.push(entry),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(chapter_id: &str, chapter: &str, path: &Path) -> HistoryEntry {
+        HistoryEntry {
+            title: String::from("The Café Terrace and Its Goddesses"),
+            chapter: Some(chapter.to_string()),
+            groups: vec![String::from("Café Scans")],
+            language: String::from("en"),
+            pages: 2,
+            checksums: vec![Some(String::from("ab")), Some(String::from("cd"))],
+            path: path.join(format!("chapter_{chapter}")),
+            downloaded_at: String::from("2023-05-01T12:00:00Z"),
+            ..HistoryEntry::new("manga-id", chapter_id)
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("library").join(HISTORY_FILE_NAME);
+        let mut history = History::open(&path).unwrap();
+        assert!(history.entries().is_empty());
+
+        history.record(entry("c1", "1", tmpdir.path())).unwrap();
+        history.record(entry("c2", "2", tmpdir.path())).unwrap();
+        let archive = tmpdir.path().join("Series.cbz");
+        fs::write(&archive, b"").unwrap();
+        let packaged = entry("c1", "1", tmpdir.path()).packaged(&archive);
+        history.record(packaged.clone()).unwrap();
+        // Another release of chapter 2, never finished
+        history.record(entry("c3", "2", tmpdir.path())).unwrap();
+
+        // Interrupted while appending
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"manga\":\"manga-id\",\"chap").unwrap();
+        drop(file);
+
+        let mut history = History::open(&path).unwrap();
+        history.record(entry("c4", "3", tmpdir.path())).unwrap();
+        let history = History::open(&path).unwrap();
+        let ids: Vec<&str> = history
+            .entries()
+            .iter()
+            .map(|e| e.chapter_id.as_str())
+            .collect();
+        assert_eq!(ids, ["c1", "c2", "c3", "c4"]);
+        assert_eq!(history.get("c1"), Some(&packaged));
+        assert!(history.get("c1").unwrap().is_present());
+        assert!(!history.get("c2").unwrap().is_present());
+
+        let found = history.find("manga-id", Some("1"), "en").unwrap();
+        assert_eq!(found.chapter_id, "c1");
+        assert_eq!(
+            history
+                .find("manga-id", Some("2"), "en")
+                .unwrap()
+                .chapter_id,
+            "c2"
+        );
+        assert!(history.find("manga-id", Some("2"), "fr").is_none());
+        assert!(history.find("manga-id", None, "en").is_none());
+    }
+}
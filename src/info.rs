@@ -0,0 +1,148 @@
+use super::model::Manga;
+use super::util::sanitize_file_name;
+use super::MangadexClient;
+use super::MangadexError;
+use getset::Getters;
+use serde::Deserialize;
+
+/// A manga with its authors, artists and cover resolved, from
+/// `GET /manga/{id}`.
+#[derive(Debug, Clone, Getters)]
+#[getset(get = "pub")]
+pub struct MangaInfo {
+    manga: Manga,
+    authors: Vec<String>,
+    artists: Vec<String>,
+    /// File name of the main cover, under `covers/{manga id}/` on the uploads
+    /// server.
+    cover: Option<String>,
+}
+
+impl MangaInfo {
+    pub async fn fetch(client: &MangadexClient, id: &str) -> Result<Self, MangadexError> {
+        #[derive(Deserialize)]
+        struct ResponseBody {
+            data: Manga,
+        }
+
+        let bytes = client
+            .get(&format!("manga/{id}"))
+            .await?
+            .query(&[
+                ("includes[]", "author"),
+                ("includes[]", "artist"),
+                ("includes[]", "cover_art"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let response: ResponseBody = serde_json::from_slice(&bytes)?;
+        Ok(Self::from(response.data))
+    }
+
+    pub fn id(&self) -> &str {
+        self.manga.id()
+    }
+
+    /// See [`Manga::title`].
+    pub fn title(&self, languages: &[&str]) -> &str {
+        self.manga.title(languages)
+    }
+
+    /// Title usable as a file name, the id when nothing of it is left.
+    pub fn folder_name(&self, languages: &[&str]) -> String {
+        match sanitize_file_name(self.title(languages)) {
+            name if name.is_empty() => self.id().to_string(),
+            name => name,
+        }
+    }
+
+    pub fn tags(&self) -> Vec<&str> {
+        self.manga
+            .attributes()
+            .tags()
+            .iter()
+            .map(|t| t.name())
+            .collect()
+    }
+}
+
+impl From<Manga> for MangaInfo {
+    fn from(manga: Manga) -> Self {
+        let names = |kind| {
+            manga
+                .relationships_of(kind)
+                .filter_map(|r| r.attribute("name"))
+                .map(str::to_string)
+                .collect()
+        };
+        let authors = names("author");
+        let artists = names("artist");
+        let cover = manga
+            .relationships_of("cover_art")
+            .find_map(|r| r.attribute("fileName"))
+            .map(str::to_string);
+        Self {
+            manga,
+            authors,
+            artists,
+            cover,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+
+    #[tokio::test]
+    async fn test_manga_info() {
+        let server = MockServer::start().await;
+        server.mount(
+            "/manga/99b8eaeb-9041-4bfd-8eb7-d72addc88eb7",
+            MockResponse::bytes(
+                "application/json",
+                include_str!("../tests/fixtures/manga.json"),
+            ),
+        );
+
+        let info = MangaInfo::fetch(&server.client(), "99b8eaeb-9041-4bfd-8eb7-d72addc88eb7")
+            .await
+            .unwrap();
+        assert_eq!(
+            server.requests()[0].query_values("includes[]"),
+            ["author", "artist", "cover_art"]
+        );
+        assert_eq!(info.authors(), &["Seo Kouji"]);
+        assert_eq!(info.artists(), &["Seo Kouji"]);
+        assert_eq!(
+            info.cover().as_deref(),
+            Some("b6c7ce9c-e671-4f26-90b0-e592188e9cd6.jpg")
+        );
+        assert_eq!(info.tags(), ["Romance", "Comedy"]);
+
+        assert_eq!(info.title(&[]), "The Café Terrace and Its Goddesses");
+        assert_eq!(info.title(&["fr"]), "The Café Terrace and Its Goddesses");
+        assert_eq!(info.title(&["ja-ro", "en"]), "Megami no Café Terrace");
+        assert_eq!(
+            info.folder_name(&["en"]),
+            "The Café Terrace and Its Goddesses"
+        );
+        assert_eq!(
+            info.manga().description(&["fr"]),
+            Some("Hayato rentre chez lui pour vendre le café de sa grand-mère.")
+        );
+        assert_eq!(
+            info.manga().description(&["de"]),
+            Some("Hayato returns home to sell his late grandmother's café.")
+        );
+
+        let result = MangaInfo::fetch(&server.client(), "unknown").await;
+        assert!(
+            matches!(result, Err(MangadexError::RequestError(e)) if e.status() == Some(reqwest::StatusCode::NOT_FOUND))
+        );
+    }
+}
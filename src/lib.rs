@@ -1,8 +1,55 @@
-mod service;
+mod auth;
+mod client;
+mod comicinfo;
+mod cover;
+mod epub;
+mod feed;
+mod follows;
+mod history;
+mod info;
+mod manifest;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+mod model;
+mod packager;
+mod pdf;
+mod policy;
+mod progress;
 mod query;
+mod report;
+mod retry;
+mod search;
+mod select;
+mod service;
+mod stream;
+mod util;
 
-pub use service::{ChapterDownloadRequest, ChapterDownloader};
+pub use auth::{MangadexAuth, DEFAULT_TOKEN_URL};
+pub use client::{MangadexClient, MangadexClientBuilder};
+pub use comicinfo::{ComicInfo, ComicPage, PageKind, COMIC_INFO_FILE_NAME};
+pub use cover::{Cover, CoverQuery, CoverSize};
+pub use feed::{ChapterInfo, MangaFeed, ScanlationGroup, MAX_FEED_LIMIT};
+pub use follows::{chapters_by_manga, FollowState, FOLLOW_STATE_FILE_NAME};
+pub use history::{History, HistoryEntry, HISTORY_FILE_NAME};
+pub use info::MangaInfo;
+pub use manifest::{ChapterManifest, PageEntry, INCOMPLETE_MARKER_FILE_NAME, MANIFEST_FILE_NAME};
+pub use model::{
+    Collection, ContentRating, Demographic, LocalizedString, Manga, MangaAttributes, MangaStatus,
+    OrderBy, OrderDirection, Relationship, Tag, TagAttributes,
+};
+pub use packager::{PackageChapter, PackageFormat, PackageMode, PackageStream, Packager};
+pub use policy::{Preference, SelectionPolicy};
+pub use progress::{ProgressEvent, ProgressListener};
 pub use query::{Chapter, GetChapters, MangaQuery, Volume};
+pub use report::NetworkReport;
+pub use retry::RetryPolicy;
+pub use search::{fetch_tags, MangaSearch};
+pub use select::{Selection, Selector};
+pub use service::{
+    ChapterDownloadReport, ChapterDownloadRequest, ChapterDownloader, PageFailure, PageReport,
+    DEFAULT_CONCURRENCY,
+};
+pub use stream::{CbzStream, ChapterStream, PageSink};
 
 #[derive(Debug, thiserror::Error)]
 pub enum MangadexError {
@@ -12,6 +59,19 @@ pub enum MangadexError {
     DeserializeError(#[from] serde_json::Error),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    ZipError(#[from] zip::result::ZipError),
+    #[error("{0}")]
+    PackageError(String),
+    #[error("authentication failed: {0}")]
+    AuthError(String),
     #[error("invalid url '{0}'")]
     UrlParseError(String),
-}
\ No newline at end of file
+    #[error(
+        "chapter {} is incomplete, {} of {} pages failed",
+        .0.id(),
+        .0.failed().len(),
+        .0.total_pages()
+    )]
+    IncompleteChapter(Box<ChapterDownloadReport>),
+}
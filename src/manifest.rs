@@ -0,0 +1,121 @@
+use super::MangadexError;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use std::fs;
+use std::path::Path;
+
+pub const MANIFEST_FILE_NAME: &str = ".mangadex-chapter.json";
+/// Left in a chapter folder accepted with missing pages, listing the failures.
+pub const INCOMPLETE_MARKER_FILE_NAME: &str = ".incomplete";
+
+/// Sidecar file kept next to downloaded pages, recording what has already
+/// been fetched so interrupted downloads can be resumed.
+#[derive(Debug, Clone, Serialize, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct ChapterManifest {
+    id: String,
+    hash: String,
+    quality: String,
+    total: usize,
+    pages: Vec<PageEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Getters, PartialEq)]
+#[getset(get = "pub")]
+pub struct PageEntry {
+    index: usize,
+    file: String,
+    source: String,
+    size: u64,
+    sha256: String,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    node: String,
+    #[serde(default)]
+    content_type: Option<String>,
+}
+
+impl ChapterManifest {
+    pub(crate) fn new(id: &str, hash: &str, quality: &str, total: usize) -> Self {
+        Self {
+            id: id.to_string(),
+            hash: hash.to_string(),
+            quality: quality.to_string(),
+            total,
+            pages: Vec::new(),
+        }
+    }
+
+    pub fn load(dir: impl AsRef<Path>) -> Option<Self> {
+        let bytes = fs::read(dir.as_ref().join(MANIFEST_FILE_NAME)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    pub(crate) fn save(&self, dir: impl AsRef<Path>) -> Result<(), MangadexError> {
+        let dir = dir.as_ref();
+        let tmp = dir.join(format!("{MANIFEST_FILE_NAME}.tmp"));
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, dir.join(MANIFEST_FILE_NAME))?;
+        Ok(())
+    }
+
+    /// Whether `dir` holds every page of a chapter, as recorded by its manifest.
+    pub fn is_complete(dir: impl AsRef<Path>) -> bool {
+        let dir = dir.as_ref();
+        Self::load(dir).is_some_and(|m| {
+            m.pages.len() == m.total && m.pages.iter().all(|p| p.is_present(dir, false))
+        })
+    }
+
+    /// Entry for page `source` whose file is still intact in `dir`.
+    pub(crate) fn verified_page(&self, dir: &Path, source: &str) -> Option<&PageEntry> {
+        self.pages
+            .iter()
+            .find(|p| p.source == source && p.is_present(dir, true))
+    }
+
+    pub(crate) fn record(&mut self, entry: PageEntry) {
+        self.pages.retain(|p| p.index != entry.index);
+        self.pages.push(entry);
+        self.pages.sort_by_key(|p| p.index);
+    }
+}
+
+impl PageEntry {
+    pub(crate) fn new(index: usize, file: &str, source: &str, bytes: &[u8]) -> Self {
+        Self {
+            index,
+            file: file.to_string(),
+            source: source.to_string(),
+            size: bytes.len() as u64,
+            sha256: sha256(bytes),
+            url: String::new(),
+            node: String::new(),
+            content_type: None,
+        }
+    }
+
+    /// Remember where the page was fetched from.
+    pub(crate) fn origin(mut self, url: &str, node: &str, content_type: Option<&str>) -> Self {
+        self.url = url.to_string();
+        self.node = node.to_string();
+        self.content_type = content_type.map(str::to_string);
+        self
+    }
+
+    fn is_present(&self, dir: &Path, verify_checksum: bool) -> bool {
+        let path = dir.join(&self.file);
+        if !fs::metadata(&path).is_ok_and(|m| m.len() == self.size) {
+            return false;
+        }
+        !verify_checksum || fs::read(&path).is_ok_and(|bytes| sha256(&bytes) == self.sha256)
+    }
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
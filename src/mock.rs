@@ -0,0 +1,389 @@
+//! Local HTTP stand-in for the mangadex API, so queries and downloads can be
+//! tested without network access.
+
+use super::MangadexClient;
+use getset::Getters;
+use reqwest::Url;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+type Handler = Box<dyn Fn(&MockRequest) -> MockResponse + Send + Sync>;
+
+#[derive(Debug, Clone, Getters)]
+#[getset(get = "pub")]
+pub struct MockRequest {
+    method: String,
+    path: String,
+    query: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    delay: Option<Duration>,
+    hold_until: Option<usize>,
+    truncate_at: Option<usize>,
+}
+
+pub struct MockServer {
+    url: String,
+    state: Arc<Mutex<State>>,
+    handle: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct State {
+    routes: Vec<(String, Handler)>,
+    requests: Vec<MockRequest>,
+    in_flight: usize,
+    max_in_flight: usize,
+}
+
+impl MockRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+
+    pub fn query_pairs(&self) -> Vec<(String, String)> {
+        Url::parse(&format!("http://localhost/?{}", self.query))
+            .map(|url| url.query_pairs().into_owned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn query_values(&self, key: &str) -> Vec<String> {
+        self.query_pairs()
+            .into_iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    /// Fields of an `application/x-www-form-urlencoded` body named `key`.
+    pub fn form_values(&self, key: &str) -> Vec<String> {
+        Url::parse(&format!(
+            "http://localhost/?{}",
+            String::from_utf8_lossy(&self.body)
+        ))
+        .map(|url| {
+            url.query_pairs()
+                .filter(|(k, _)| k == key)
+                .map(|(_, v)| v.into_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+    }
+}
+
+impl MockResponse {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            delay: None,
+            hold_until: None,
+            truncate_at: None,
+        }
+    }
+
+    pub fn json(value: serde_json::Value) -> Self {
+        Self::new(200)
+            .header("content-type", "application/json")
+            .body(value.to_string())
+    }
+
+    pub fn bytes(content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        Self::new(200)
+            .header("content-type", content_type)
+            .body(body)
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(404)
+            .header("content-type", "application/json")
+            .body(json!({"result": "error", "errors": [{"status": 404}]}).to_string())
+    }
+
+    pub fn too_many_requests(retry_after: u64) -> Self {
+        Self::new(429).header("retry-after", retry_after.to_string())
+    }
+
+    pub fn header(mut self, name: &str, value: impl ToString) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Wait before sending anything back.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Wait until `in_flight` requests are being handled at the same time, or
+    /// for two seconds at most, before sending anything back.
+    pub fn hold_until(mut self, in_flight: usize) -> Self {
+        self.hold_until = Some(in_flight);
+        self
+    }
+
+    /// Advertise the full body length but close the connection after `len` bytes.
+    pub fn truncated(mut self, len: usize) -> Self {
+        self.truncate_at = Some(len);
+        self
+    }
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let handle = tokio::spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(handle_connection(stream, state.clone()));
+                }
+            }
+        });
+
+        let server = Self { url, state, handle };
+        server.mount("/report", MockResponse::new(200));
+        server
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn client(&self) -> MangadexClient {
+        MangadexClient::builder()
+            .base_url(&self.url)
+            .uploads_url(&self.url)
+            .report_url(format!("{}/report", self.url))
+            .build()
+            .expect("mock server url is valid")
+    }
+
+    /// Serve `response` for every request to `path`. Later mounts take
+    /// precedence over earlier ones for the same path.
+    pub fn mount(&self, path: &str, response: MockResponse) {
+        self.mount_with(path, move |_| response.clone());
+    }
+
+    /// Serve `responses` in turn, repeating the last one once exhausted.
+    pub fn mount_sequence(&self, path: &str, responses: Vec<MockResponse>) {
+        let served = Mutex::new(0usize);
+        self.mount_with(path, move |_| {
+            let mut served = served.lock().unwrap();
+            let response = responses
+                .get(*served)
+                .or_else(|| responses.last())
+                .cloned()
+                .unwrap_or_else(MockResponse::not_found);
+            *served += 1;
+            response
+        });
+    }
+
+    pub fn mount_with<F>(&self, path: &str, handler: F)
+    where
+        F: Fn(&MockRequest) -> MockResponse + Send + Sync + 'static,
+    {
+        self.state
+            .lock()
+            .unwrap()
+            .routes
+            .push((path.to_string(), Box::new(handler)));
+    }
+
+    /// Serve a chapter through `/at-home/server/{id}` with the given pages,
+    /// available in both `data` and `data-saver` qualities.
+    pub fn mount_chapter(&self, id: &str, hash: &str, pages: &[(&str, &[u8])]) {
+        let files: Vec<&str> = pages.iter().map(|(name, _)| *name).collect();
+        self.mount(
+            &format!("/at-home/server/{id}"),
+            MockResponse::json(json!({
+                "result": "ok",
+                "baseUrl": self.url,
+                "chapter": {
+                    "hash": hash,
+                    "data": files,
+                    "dataSaver": files,
+                }
+            })),
+        );
+        for (name, bytes) in pages {
+            let content_type = if name.ends_with(".png") {
+                "image/png"
+            } else {
+                "image/jpeg"
+            };
+            for quality in ["data", "data-saver"] {
+                self.mount(
+                    &format!("/{quality}/{hash}/{name}"),
+                    MockResponse::bytes(content_type, *bytes),
+                );
+            }
+        }
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Highest number of requests handled at the same time so far.
+    pub fn max_in_flight(&self) -> usize {
+        self.state.lock().unwrap().max_in_flight
+    }
+
+    pub fn hits(&self, path: &str) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .requests
+            .iter()
+            .filter(|r| r.path == path)
+            .count()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, state: Arc<Mutex<State>>) {
+    let Some(request) = read_request(&mut stream).await else {
+        return;
+    };
+
+    let response = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(request.clone());
+        state.in_flight += 1;
+        state.max_in_flight = state.max_in_flight.max(state.in_flight);
+        state
+            .routes
+            .iter()
+            .rev()
+            .find(|(path, _)| *path == request.path)
+            .map(|(_, handler)| handler(&request))
+            .unwrap_or_else(MockResponse::not_found)
+    };
+
+    if let Some(in_flight) = response.hold_until {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+        while state.lock().unwrap().in_flight < in_flight && tokio::time::Instant::now() < deadline
+        {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+    if let Some(delay) = response.delay {
+        tokio::time::sleep(delay).await;
+    }
+
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\ncontent-length: {}\r\nconnection: close\r\n",
+        response.status,
+        reason(response.status),
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+
+    let body = match response.truncate_at {
+        Some(len) => &response.body[..len.min(response.body.len())],
+        None => &response.body[..],
+    };
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(body).await;
+    let _ = stream.shutdown().await;
+    state.lock().unwrap().in_flight -= 1;
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<MockRequest> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+        .collect();
+
+    let content_length: usize = headers
+        .get("content-length")
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(0);
+    let mut body = buf[header_end + 4..].to_vec();
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+
+    Some(MockRequest {
+        method,
+        path: path.to_string(),
+        query: query.to_string(),
+        headers,
+        body,
+    })
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
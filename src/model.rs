@@ -0,0 +1,240 @@
+use getset::Getters;
+use serde::Deserialize;
+use serde::Deserializer;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Text keyed by language code, e.g. `{"en": "...", "ja-ro": "..."}`.
+pub type LocalizedString = HashMap<String, String>;
+
+/// Paginated list returned by the collection endpoints.
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct Collection<T> {
+    data: Vec<T>,
+    limit: usize,
+    offset: usize,
+    total: usize,
+}
+
+impl<T> Collection<T> {
+    pub fn into_data(self) -> Vec<T> {
+        self.data
+    }
+
+    /// Whether more items are available after this page.
+    pub fn has_more(&self) -> bool {
+        self.offset + self.data.len() < self.total
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct Manga {
+    id: String,
+    attributes: MangaAttributes,
+    #[serde(default)]
+    relationships: Vec<Relationship>,
+}
+
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[serde(rename_all = "camelCase")]
+#[getset(get = "pub")]
+pub struct MangaAttributes {
+    #[serde(deserialize_with = "deserialize_localized")]
+    title: LocalizedString,
+    #[serde(default)]
+    alt_titles: Vec<LocalizedString>,
+    #[serde(default, deserialize_with = "deserialize_localized")]
+    description: LocalizedString,
+    original_language: Option<String>,
+    last_volume: Option<String>,
+    last_chapter: Option<String>,
+    publication_demographic: Option<Demographic>,
+    status: Option<MangaStatus>,
+    year: Option<u32>,
+    content_rating: Option<ContentRating>,
+    #[serde(default)]
+    tags: Vec<Tag>,
+    #[serde(default)]
+    available_translated_languages: Vec<Option<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct Tag {
+    id: String,
+    attributes: TagAttributes,
+}
+
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct TagAttributes {
+    #[serde(deserialize_with = "deserialize_localized")]
+    name: LocalizedString,
+    group: String,
+}
+
+/// Related entity, such as an author or a cover. `attributes` is only filled
+/// when the relationship was requested with `includes[]`.
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct Relationship {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    related: Option<String>,
+    attributes: Option<serde_json::Value>,
+}
+
+impl Manga {
+    /// Title in the first of `languages` it exists in, alternative titles
+    /// included, or the main title otherwise.
+    pub fn title(&self, languages: &[&str]) -> &str {
+        let attributes = &self.attributes;
+        languages
+            .iter()
+            .find_map(|&language| {
+                attributes.title.get(language).or_else(|| {
+                    attributes
+                        .alt_titles
+                        .iter()
+                        .find_map(|titles| titles.get(language))
+                })
+            })
+            .or_else(|| localized(&attributes.title, &[]))
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+
+    /// Description in the first of `languages` it exists in, or in english.
+    pub fn description(&self, languages: &[&str]) -> Option<&str> {
+        localized(&self.attributes.description, languages).map(String::as_str)
+    }
+
+    pub fn relationships_of<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a Relationship> {
+        self.relationships.iter().filter(move |r| r.kind == kind)
+    }
+}
+
+impl Relationship {
+    /// String attribute of an included relationship, such as an author `name`.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.as_ref()?.get(key)?.as_str()
+    }
+}
+
+impl Tag {
+    /// English name of the tag, which every tag has.
+    pub fn name(&self) -> &str {
+        localized(&self.attributes.name, &[])
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+}
+
+/// Value in the first of `languages`, then in english, then in any language.
+fn localized<'a>(value: &'a LocalizedString, languages: &[&str]) -> Option<&'a String> {
+    languages
+        .iter()
+        .chain(&["en"])
+        .find_map(|&language| value.get(language))
+        .or_else(|| value.values().next())
+}
+
+/// Localized values are sent as `[]` instead of `{}` when empty.
+pub(crate) fn deserialize_localized<'de, D>(deserializer: D) -> Result<LocalizedString, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Localized {
+        Map(LocalizedString),
+        Empty([String; 0]),
+    }
+
+    match Localized::deserialize(deserializer)? {
+        Localized::Map(map) => Ok(map),
+        Localized::Empty(_) => Ok(LocalizedString::new()),
+    }
+}
+
+macro_rules! string_enum {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident => $value:literal),+ $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+        pub enum $name {
+            $(#[serde(rename = $value)] $variant),+
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(Self::$variant => $value),+
+                }
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($value => Ok(Self::$variant),)+
+                    _ => Err(format!(
+                        "'{s}' is not one of: {}",
+                        [$($value),+].join(", ")
+                    )),
+                }
+            }
+        }
+    };
+}
+
+string_enum!(MangaStatus {
+    Ongoing => "ongoing",
+    Completed => "completed",
+    Hiatus => "hiatus",
+    Cancelled => "cancelled",
+});
+
+string_enum!(ContentRating {
+    Safe => "safe",
+    Suggestive => "suggestive",
+    Erotica => "erotica",
+    Pornographic => "pornographic",
+});
+
+string_enum!(Demographic {
+    Shounen => "shounen",
+    Shoujo => "shoujo",
+    Josei => "josei",
+    Seinen => "seinen",
+});
+
+string_enum!(
+    /// Field to sort search results by.
+    OrderBy {
+        Relevance => "relevance",
+        Title => "title",
+        Year => "year",
+        CreatedAt => "createdAt",
+        UpdatedAt => "updatedAt",
+        LatestUploadedChapter => "latestUploadedChapter",
+        FollowedCount => "followedCount",
+        Rating => "rating",
+    }
+);
+
+string_enum!(OrderDirection {
+    Asc => "asc",
+    Desc => "desc",
+});
@@ -0,0 +1,818 @@
+use super::comicinfo::ComicInfo;
+use super::epub::write_epub;
+use super::manifest::INCOMPLETE_MARKER_FILE_NAME;
+use super::pdf::pdf_chapters;
+use super::pdf::write_pdf;
+use super::stream::CbzStream;
+use super::stream::ChapterStream;
+use super::stream::PageSink;
+use super::util::sanitize_file_name;
+use super::MangadexError;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::warn;
+use zip::ZipArchive;
+
+/// How downloaded chapter folders are bundled into cbz archives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PackageMode {
+    /// Leave the chapter folders as they are.
+    #[default]
+    None,
+    /// Every chapter in one archive.
+    Single,
+    PerVolume,
+    PerChapter,
+}
+
+impl PackageMode {
+    /// Archive name used when no template is given.
+    pub fn default_template(&self) -> &'static str {
+        match self {
+            Self::None | Self::Single => "{series}",
+            Self::PerVolume => "{series} - Volume {volume}",
+            Self::PerChapter => "{series} - Chapter {chapter}",
+        }
+    }
+}
+
+impl FromStr for PackageMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "single" => Ok(Self::Single),
+            "volume" => Ok(Self::PerVolume),
+            "chapter" => Ok(Self::PerChapter),
+            _ => Err(format!(
+                "'{s}' is not one of: none, single, volume, chapter"
+            )),
+        }
+    }
+}
+
+/// File format of the archives written by a [`Packager`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PackageFormat {
+    #[default]
+    Cbz,
+    /// Fixed-layout EPUB 3, for e-readers.
+    Epub,
+    /// A page per image, with a bookmark per chapter.
+    Pdf,
+}
+
+impl PackageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Cbz => "cbz",
+            Self::Epub => "epub",
+            Self::Pdf => "pdf",
+        }
+    }
+}
+
+impl FromStr for PackageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cbz" => Ok(Self::Cbz),
+            "epub" => Ok(Self::Epub),
+            "pdf" => Ok(Self::Pdf),
+            _ => Err(format!("'{s}' is not one of: cbz, epub, pdf")),
+        }
+    }
+}
+
+/// A downloaded chapter folder.
+#[derive(Debug, Clone)]
+pub struct PackageChapter {
+    pub(crate) path: PathBuf,
+    pub(crate) chapter: Option<String>,
+    pub(crate) volume: Option<String>,
+    pub(crate) title: Option<String>,
+}
+
+impl PackageChapter {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            chapter: None,
+            volume: None,
+            title: None,
+        }
+    }
+
+    pub fn chapter(mut self, chapter: impl ToString) -> Self {
+        self.chapter = Some(chapter.to_string());
+        self
+    }
+
+    pub fn volume(mut self, volume: impl ToString) -> Self {
+        self.volume = Some(volume.to_string());
+        self
+    }
+
+    pub fn title(mut self, title: impl ToString) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn folder_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Name of the chapter in a table of contents.
+    pub(crate) fn label(&self) -> String {
+        match (&self.chapter, &self.title) {
+            (Some(chapter), Some(title)) => format!("Chapter {chapter}: {title}"),
+            (Some(chapter), None) => format!("Chapter {chapter}"),
+            (None, Some(title)) => title.clone(),
+            (None, None) => self.folder_name(),
+        }
+    }
+}
+
+/// Bundles chapter folders into cbz, epub or pdf archives named after a
+/// template.
+///
+/// Templates may use `{series}`, `{volume}`, `{chapter}` and `{title}`.
+/// Archives holding several chapters keep each one in its own folder, and
+/// chapters packaged into an archive that already exists are added after the
+/// ones it holds, or take the place of the same chapter. A per-chapter archive
+/// is replaced instead, its name is made unique with the chapter folder when
+/// the template or the chapter number cannot tell chapters apart. Packaged
+/// folders are deleted, unless some of their pages failed to download or could
+/// not be put in the archive.
+#[derive(Debug, Clone)]
+pub struct Packager {
+    pub(crate) mode: PackageMode,
+    pub(crate) format: PackageFormat,
+    pub(crate) series: String,
+    pub(crate) template: Option<String>,
+    pub(crate) comic_info: Option<ComicInfo>,
+    pub(crate) covers: HashMap<Option<String>, PathBuf>,
+}
+
+impl Packager {
+    pub fn new(mode: PackageMode, series: impl ToString) -> Self {
+        Self {
+            mode,
+            format: PackageFormat::default(),
+            series: series.to_string(),
+            template: None,
+            comic_info: None,
+            covers: HashMap::new(),
+        }
+    }
+
+    pub fn format(mut self, format: PackageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn template(mut self, template: impl ToString) -> Self {
+        self.template = Some(template.to_string());
+        self
+    }
+
+    /// Series metadata written to every archive, completed with the number,
+    /// volume and title of what the archive holds.
+    pub fn comic_info(mut self, comic_info: ComicInfo) -> Self {
+        self.comic_info = Some(comic_info);
+        self
+    }
+
+    /// Image put first in archives of `volume`, `None` for chapters without a
+    /// volume or volumes without a cover of their own.
+    pub fn cover(mut self, volume: Option<impl ToString>, path: impl AsRef<Path>) -> Self {
+        self.covers
+            .insert(volume.map(|v| v.to_string()), path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn mode(&self) -> PackageMode {
+        self.mode
+    }
+
+    /// File name of the archive `chapter` goes into, `None` when not packaging.
+    pub fn archive_name(&self, chapter: &PackageChapter) -> Option<String> {
+        if self.mode == PackageMode::None {
+            return None;
+        }
+        let template = self
+            .template
+            .as_deref()
+            .unwrap_or(self.mode.default_template());
+        let mut name = template
+            .replace("{series}", &self.series)
+            .replace("{volume}", chapter.volume.as_deref().unwrap_or("none"))
+            .replace("{chapter}", chapter.chapter.as_deref().unwrap_or("none"))
+            .replace("{title}", chapter.title.as_deref().unwrap_or_default());
+        if self.mode == PackageMode::PerChapter
+            && (chapter.chapter.is_none() || !template.contains("{chapter}"))
+        {
+            name = format!("{name} ({})", chapter.folder_name());
+        }
+        Some(format!(
+            "{}.{}",
+            sanitize_file_name(&name),
+            self.format.extension()
+        ))
+    }
+
+    /// Whether `chapter` is already in its archive under `dir`.
+    pub fn is_packaged(&self, dir: impl AsRef<Path>, chapter: &PackageChapter) -> bool {
+        let Some(name) = self.archive_name(chapter) else {
+            return false;
+        };
+        let path = dir.as_ref().join(name);
+        match self.mode {
+            PackageMode::PerChapter => path.exists(),
+            _ => archived_chapters(&path).is_ok_and(|c| c.contains(&chapter.folder_name())),
+        }
+    }
+
+    /// Package `chapters` into archives under `dir`, returning the archives
+    /// written.
+    pub fn package(
+        &self,
+        dir: impl AsRef<Path>,
+        chapters: &[PackageChapter],
+    ) -> Result<Vec<PathBuf>, MangadexError> {
+        let mut written = Vec::new();
+        for (name, chapters) in self.group(chapters)? {
+            let (comic_info, cover) = self.archive_metadata(chapters[0]);
+            let path = dir.as_ref().join(name);
+            let incomplete = match self.format {
+                PackageFormat::Cbz => {
+                    let folders: Vec<&Path> = chapters.iter().map(|c| c.path.as_path()).collect();
+                    write_cbz(
+                        &path,
+                        cover,
+                        comic_info,
+                        &folders,
+                        self.mode == PackageMode::PerChapter,
+                    )?;
+                    Vec::new()
+                }
+                PackageFormat::Epub => {
+                    write_epub(
+                        &path,
+                        cover,
+                        &comic_info,
+                        &chapters,
+                        self.mode != PackageMode::PerChapter,
+                    )?;
+                    Vec::new()
+                }
+                PackageFormat::Pdf => write_pdf(
+                    &path,
+                    cover,
+                    &comic_info,
+                    &chapters,
+                    self.mode != PackageMode::PerChapter,
+                )?,
+            };
+            for chapter in chapters {
+                // Pages left out of the archive only remain in the folder, and
+                // pages that failed to download are resumed from it
+                if incomplete.contains(&chapter.path) {
+                    warn!(
+                        "Keep {}, some of its pages are missing from {}",
+                        chapter.path.display(),
+                        path.display()
+                    );
+                    continue;
+                }
+                if chapter.path.join(INCOMPLETE_MARKER_FILE_NAME).exists() {
+                    warn!(
+                        "Keep {}, some of its pages failed to download",
+                        chapter.path.display()
+                    );
+                    continue;
+                }
+                let _ = fs::remove_dir_all(&chapter.path);
+            }
+            written.push(path);
+        }
+        Ok(written)
+    }
+
+    /// Open the cbz archives `chapters` go into under `dir`, to be filled
+    /// while the chapters download instead of packaging their folders
+    /// afterwards.
+    pub fn stream(
+        &self,
+        dir: impl AsRef<Path>,
+        chapters: &[PackageChapter],
+    ) -> Result<PackageStream, MangadexError> {
+        if self.mode == PackageMode::None || self.format != PackageFormat::Cbz {
+            return Err(MangadexError::PackageError(String::from(
+                "only cbz archives can be written while downloading",
+            )));
+        }
+        let mut archives = Vec::new();
+        let mut sinks = HashMap::new();
+        for (name, chapters) in self.group(chapters)? {
+            let (comic_info, cover) = self.archive_metadata(chapters[0]);
+            let archive = Arc::new(CbzStream::create(
+                &dir.as_ref().join(name),
+                cover,
+                comic_info,
+                self.mode == PackageMode::PerChapter,
+            )?);
+            for chapter in chapters {
+                sinks.insert(
+                    chapter.path.clone(),
+                    archive.chapter(&chapter.folder_name()),
+                );
+            }
+            archives.push(archive);
+        }
+        Ok(PackageStream {
+            archives,
+            sinks,
+            copied: Vec::new(),
+        })
+    }
+
+    /// Chapters by the name of the archive they go into, in the order given.
+    fn group<'a>(
+        &self,
+        chapters: &'a [PackageChapter],
+    ) -> Result<Vec<(String, Vec<&'a PackageChapter>)>, MangadexError> {
+        let mut archives: Vec<(String, Vec<&PackageChapter>)> = Vec::new();
+        for chapter in chapters {
+            let Some(name) = self.archive_name(chapter) else {
+                continue;
+            };
+            match archives.iter_mut().find(|(n, _)| *n == name) {
+                // Pages of a per-chapter archive sit at the root and would clash
+                Some((_, others)) if self.mode == PackageMode::PerChapter => {
+                    return Err(MangadexError::PackageError(format!(
+                        "{} and {} would both be packaged into {name}",
+                        others[0].path.display(),
+                        chapter.path.display()
+                    )));
+                }
+                Some((_, chapters)) => chapters.push(chapter),
+                None => archives.push((name, vec![chapter])),
+            }
+        }
+        Ok(archives)
+    }
+
+    /// Metadata and cover of the archive starting with `first`.
+    fn archive_metadata(&self, first: &PackageChapter) -> (ComicInfo, Option<&Path>) {
+        let mut comic_info = self
+            .comic_info
+            .clone()
+            .unwrap_or_else(|| ComicInfo::new(&self.series));
+        if self.mode != PackageMode::Single {
+            if let Some(volume) = &first.volume {
+                comic_info = comic_info.volume(volume);
+            }
+        }
+        if self.mode == PackageMode::PerChapter {
+            if let Some(chapter) = &first.chapter {
+                comic_info = comic_info.number(chapter);
+            }
+            if let Some(title) = &first.title {
+                comic_info = comic_info.title(title);
+            }
+        }
+        let cover = self
+            .covers
+            .get(&first.volume)
+            .or_else(|| self.covers.get(&None))
+            .map(PathBuf::as_path);
+        (comic_info, cover)
+    }
+}
+
+/// Archives opened by [`Packager::stream`].
+#[derive(Debug)]
+pub struct PackageStream {
+    archives: Vec<Arc<CbzStream>>,
+    sinks: HashMap<PathBuf, ChapterStream>,
+    /// Folders already downloaded, copied into the archives
+    copied: Vec<PathBuf>,
+}
+
+impl PackageStream {
+    /// Where the pages of the chapter with folder `path` go.
+    pub fn sink(&self, path: impl AsRef<Path>) -> Option<ChapterStream> {
+        self.sinks.get(path.as_ref()).cloned()
+    }
+
+    /// Copy the chapter folder `path`, downloaded before, into its archive.
+    /// The folder is deleted once the archive is finished.
+    pub fn copy_folder(&mut self, path: impl AsRef<Path>) -> Result<(), MangadexError> {
+        let path = path.as_ref();
+        let Some(sink) = self.sinks.get(path) else {
+            return Ok(());
+        };
+        copy_folder(sink, path)?;
+        self.copied.push(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Finish every archive, returning those written.
+    pub fn finish(self) -> Result<Vec<PathBuf>, MangadexError> {
+        let mut written = Vec::new();
+        for archive in self.archives {
+            written.extend(archive.finish()?);
+        }
+        for folder in self.copied {
+            let _ = fs::remove_dir_all(folder);
+        }
+        Ok(written)
+    }
+}
+
+/// Names of the chapter folders already packed into the cbz, epub or pdf
+/// archive at `path`.
+pub(crate) fn archived_chapters(path: impl AsRef<Path>) -> Result<HashSet<String>, MangadexError> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    if path.extension().is_some_and(|ext| ext == "pdf") {
+        return Ok(pdf_chapters(path)?.into_iter().collect());
+    }
+    let archive = ZipArchive::new(fs::File::open(path)?)?;
+    // Pages sit in a chapter folder, at the root of cbz archives and under
+    // `OEBPS/images` in epub ones
+    Ok(archive
+        .file_names()
+        .filter_map(|name| name.rsplit_once('/'))
+        .map(|(folders, _)| folders.rsplit('/').next().unwrap_or(folders))
+        .filter_map(chapter_folder_name)
+        .map(str::to_string)
+        .collect())
+}
+
+/// Name of the chapter folder packed as `folder`, a `{:05}_{chapter}` folder of
+/// an archive.
+pub(crate) fn chapter_folder_name(folder: &str) -> Option<&str> {
+    let (index, chapter_name) = folder.split_once('_')?;
+    (index.len() == 5 && index.bytes().all(|b| b.is_ascii_digit()) && !chapter_name.is_empty())
+        .then_some(chapter_name)
+}
+
+/// Write `folders` into `cbz_path` along with a `ComicInfo.xml` describing
+/// every page, `cover` first. Pages go at the root when `flat`, in a folder per
+/// chapter otherwise, after the chapters of the archive already there or in
+/// place of the same chapter.
+fn write_cbz(
+    cbz_path: &Path,
+    cover: Option<&Path>,
+    comic_info: ComicInfo,
+    folders: &[&Path],
+    flat: bool,
+) -> Result<(), MangadexError> {
+    let archive = Arc::new(CbzStream::create(cbz_path, cover, comic_info, flat)?);
+    for folder in folders {
+        let name = folder.file_name().unwrap_or_default().to_string_lossy();
+        copy_folder(&archive.chapter(&name), folder)?;
+    }
+    archive.finish()?;
+    Ok(())
+}
+
+/// Hand the pages of the chapter folder `folder` to `sink`, along with the
+/// pages missing from it.
+fn copy_folder(sink: &dyn PageSink, folder: &Path) -> Result<(), MangadexError> {
+    let mut files: Vec<PathBuf> = fs::read_dir(folder)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    files.sort();
+    for file_path in files {
+        let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+        if !file_path.is_file() || file_name.starts_with('.') {
+            continue;
+        }
+        sink.write_page(&file_name, &fs::read(&file_path)?)?;
+    }
+    if let Ok(failures) = fs::read_to_string(folder.join(INCOMPLETE_MARKER_FILE_NAME)) {
+        sink.incomplete(&failures);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::comicinfo::COMIC_INFO_FILE_NAME;
+    use std::io::Read;
+
+    /// Downloaded chapter folder `name` under `dir` holding `pages`, by file
+    /// name.
+    pub(crate) fn chapter_with(dir: &Path, name: &str, pages: &[(&str, &[u8])]) -> PathBuf {
+        let path = dir.join(name);
+        fs::create_dir_all(&path).unwrap();
+        for (file_name, bytes) in pages {
+            fs::write(path.join(file_name), bytes).unwrap();
+        }
+        fs::write(path.join(".mangadex-chapter.json"), "{}").unwrap();
+        path
+    }
+
+    fn chapter(dir: &Path, name: &str, pages: usize) -> PathBuf {
+        let pages: Vec<(String, Vec<u8>)> = (0..pages)
+            .map(|i| (format!("page_{i}.jpg"), vec![i as u8; 10 + i]))
+            .collect();
+        let pages: Vec<(&str, &[u8])> = pages
+            .iter()
+            .map(|(name, bytes)| (name.as_str(), bytes.as_slice()))
+            .collect();
+        chapter_with(dir, name, &pages)
+    }
+
+    fn entries(path: &Path) -> Vec<String> {
+        let archive = ZipArchive::new(fs::File::open(path).unwrap()).unwrap();
+        let mut names: Vec<String> = archive.file_names().map(str::to_string).collect();
+        names.sort();
+        names
+    }
+
+    fn comic_info(path: &Path) -> String {
+        let mut archive = ZipArchive::new(fs::File::open(path).unwrap()).unwrap();
+        let mut xml = String::new();
+        archive
+            .by_name(COMIC_INFO_FILE_NAME)
+            .unwrap()
+            .read_to_string(&mut xml)
+            .unwrap();
+        xml
+    }
+
+    #[test]
+    fn test_archive_name() {
+        let chapter = PackageChapter::new("chapter_03")
+            .chapter("03")
+            .volume(1)
+            .title("A/B");
+        let packager = |mode| Packager::new(mode, "Café: Terrace");
+        assert_eq!(packager(PackageMode::None).archive_name(&chapter), None);
+        assert_eq!(
+            packager(PackageMode::Single)
+                .archive_name(&chapter)
+                .unwrap(),
+            "Café_ Terrace.cbz"
+        );
+        assert_eq!(
+            packager(PackageMode::PerVolume)
+                .archive_name(&chapter)
+                .unwrap(),
+            "Café_ Terrace - Volume 1.cbz"
+        );
+        assert_eq!(
+            packager(PackageMode::PerChapter)
+                .template("{chapter} {title}")
+                .archive_name(&chapter)
+                .unwrap(),
+            "03 A_B.cbz"
+        );
+    }
+
+    #[test]
+    fn test_package_per_chapter() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("cover.jpg"), b"cover").unwrap();
+        let chapters = vec![
+            PackageChapter::new(chapter(dir.path(), "chapter_1", 2))
+                .chapter(1)
+                .volume(1)
+                .title("Start"),
+            PackageChapter::new(chapter(dir.path(), "chapter_2", 1)).chapter(2),
+        ];
+        let packager = Packager::new(PackageMode::PerChapter, "Series")
+            .cover(Some(1), dir.path().join("cover.jpg"));
+        assert!(!packager.is_packaged(dir.path(), &chapters[0]));
+
+        let written = packager.package(dir.path(), &chapters).unwrap();
+        assert_eq!(
+            written,
+            [
+                dir.path().join("Series - Chapter 1.cbz"),
+                dir.path().join("Series - Chapter 2.cbz")
+            ]
+        );
+        assert!(packager.is_packaged(dir.path(), &chapters[0]));
+        assert!(!dir.path().join("chapter_1").exists());
+        assert_eq!(
+            entries(&written[0]),
+            [
+                "00000_cover.jpg",
+                "ComicInfo.xml",
+                "page_0.jpg",
+                "page_1.jpg"
+            ]
+        );
+        let xml = comic_info(&written[0]);
+        assert!(xml.contains("<Title>Start</Title>"));
+        assert!(xml.contains("<Number>1</Number>"));
+        assert!(xml.contains("<Volume>1</Volume>"));
+        assert!(xml.contains("<Page Image=\"0\" ImageSize=\"5\" Type=\"FrontCover\" />"));
+        assert!(xml.contains("<Page Image=\"2\" ImageSize=\"11\" Type=\"Story\" />"));
+        assert_eq!(entries(&written[1]), ["ComicInfo.xml", "page_0.jpg"]);
+    }
+
+    #[test]
+    fn test_package_per_chapter_epub_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let packager = Packager::new(PackageMode::PerChapter, "Series").format(PackageFormat::Epub);
+        for _ in 0..2 {
+            let chapters = [PackageChapter::new(chapter(dir.path(), "chapter_1", 2)).chapter(1)];
+            packager.package(dir.path(), &chapters).unwrap();
+        }
+
+        let images: Vec<String> = entries(&dir.path().join("Series - Chapter 1.epub"))
+            .into_iter()
+            .filter(|name| name.starts_with("OEBPS/images/"))
+            .collect();
+        assert_eq!(
+            images,
+            [
+                "OEBPS/images/00000_chapter_1/page_0.jpg",
+                "OEBPS/images/00000_chapter_1/page_1.jpg"
+            ]
+        );
+        // Without a cover there is nothing for the EPUB 2 cover to point to
+        let mut archive =
+            ZipArchive::new(fs::File::open(dir.path().join("Series - Chapter 1.epub")).unwrap())
+                .unwrap();
+        let mut opf = String::new();
+        archive
+            .by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        assert!(!opf.contains("<meta name=\"cover\""));
+    }
+
+    #[test]
+    fn test_package_per_volume_appends() {
+        let dir = tempfile::tempdir().unwrap();
+        let packager = Packager::new(PackageMode::PerVolume, "Series");
+        let first = [PackageChapter::new(chapter(dir.path(), "chapter_1", 2)).volume(1)];
+        packager.package(dir.path(), &first).unwrap();
+
+        let second = [
+            PackageChapter::new(chapter(dir.path(), "chapter_2", 1)).volume(1),
+            PackageChapter::new(chapter(dir.path(), "chapter_3", 1)).volume(2),
+        ];
+        assert!(packager.is_packaged(dir.path(), &first[0]));
+        assert!(!packager.is_packaged(dir.path(), &second[0]));
+        let written = packager.package(dir.path(), &second).unwrap();
+        assert_eq!(written.len(), 2);
+
+        let volume_1 = dir.path().join("Series - Volume 1.cbz");
+        assert_eq!(
+            entries(&volume_1),
+            [
+                "00000_chapter_1/page_0.jpg",
+                "00000_chapter_1/page_1.jpg",
+                "00001_chapter_2/page_0.jpg",
+                "ComicInfo.xml"
+            ]
+        );
+        assert!(comic_info(&volume_1).contains("<PageCount>3</PageCount>"));
+        assert_eq!(
+            archived_chapters(&volume_1).unwrap(),
+            HashSet::from(["chapter_1".to_string(), "chapter_2".to_string()])
+        );
+        assert_eq!(
+            entries(&dir.path().join("Series - Volume 2.cbz")),
+            ["00000_chapter_3/page_0.jpg", "ComicInfo.xml"]
+        );
+    }
+
+    #[test]
+    fn test_package_volume_covers() {
+        let dir = tempfile::tempdir().unwrap();
+        let packager = Packager::new(PackageMode::PerVolume, "Series")
+            .cover(Some(1), dir.path().join("cover_volume_1.jpg"))
+            .cover(Some(2), dir.path().join("cover_volume_2.jpg"))
+            .cover(None::<f32>, dir.path().join("cover.jpg"));
+        for name in ["cover_volume_1", "cover_volume_2", "cover"] {
+            fs::write(dir.path().join(format!("{name}.jpg")), name).unwrap();
+        }
+        let chapters = [
+            PackageChapter::new(chapter(dir.path(), "chapter_1", 1)).volume(1),
+            PackageChapter::new(chapter(dir.path(), "chapter_2", 1)).volume(2),
+            PackageChapter::new(chapter(dir.path(), "chapter_3", 1)).volume(3),
+            PackageChapter::new(chapter(dir.path(), "chapter_4", 1)),
+        ];
+        let written = packager.package(dir.path(), &chapters).unwrap();
+        assert_eq!(written.len(), 4);
+
+        // Each archive opens on the cover of its own volume, or the main one
+        for (path, cover) in
+            written
+                .iter()
+                .zip(["cover_volume_1", "cover_volume_2", "cover", "cover"])
+        {
+            let mut archive = ZipArchive::new(fs::File::open(path).unwrap()).unwrap();
+            let mut first = archive.by_index(0).unwrap();
+            assert_eq!(first.name(), "00000_cover.jpg");
+            let mut content = String::new();
+            first.read_to_string(&mut content).unwrap();
+            assert_eq!(content, cover, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn test_archived_chapters_foreign_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Series.cbz");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        for name in [
+            "日本語/p.jpg",
+            "00000_chapter_1/p.jpg",
+            "0000é_chapter_2/p.jpg",
+            "00001_/p.jpg",
+            "000001_chapter_3/p.jpg",
+        ] {
+            writer
+                .start_file(name, zip::write::FileOptions::default())
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        assert_eq!(
+            archived_chapters(&path).unwrap(),
+            HashSet::from(["chapter_1".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_package_per_chapter_unique_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let chapters = [
+            PackageChapter::new(chapter(dir.path(), "chapter_none_a", 1)).title("Extra"),
+            PackageChapter::new(chapter(dir.path(), "chapter_none_b", 2)).title("Extra"),
+            PackageChapter::new(chapter(dir.path(), "chapter_1", 1)).chapter(1),
+        ];
+        let packager = Packager::new(PackageMode::PerChapter, "Series");
+        let written = packager.package(dir.path(), &chapters).unwrap();
+        assert_eq!(
+            written,
+            [
+                dir.path()
+                    .join("Series - Chapter none (chapter_none_a).cbz"),
+                dir.path()
+                    .join("Series - Chapter none (chapter_none_b).cbz"),
+                dir.path().join("Series - Chapter 1.cbz"),
+            ]
+        );
+        assert_eq!(
+            entries(&written[1]),
+            ["ComicInfo.xml", "page_0.jpg", "page_1.jpg"]
+        );
+
+        // A template without the chapter number
+        let packager = packager.template("{series} - {title}");
+        let chapters = [
+            PackageChapter::new(chapter(dir.path(), "chapter_2", 1))
+                .chapter(2)
+                .title("Extra"),
+            PackageChapter::new(chapter(dir.path(), "chapter_3", 1))
+                .chapter(3)
+                .title("Extra"),
+        ];
+        assert_eq!(
+            packager.archive_name(&chapters[0]).unwrap(),
+            "Series - Extra (chapter_2).cbz"
+        );
+        assert_eq!(packager.package(dir.path(), &chapters).unwrap().len(), 2);
+
+        // Chapters still sharing an archive are refused, their folders are kept
+        let chapters = [
+            PackageChapter::new(chapter(dir.path(), "chapter_4", 1)).chapter(4),
+            PackageChapter::new(chapter(&dir.path().join("other"), "chapter_4", 1)).chapter(4),
+        ];
+        let packager = Packager::new(PackageMode::PerChapter, "Series");
+        assert!(matches!(
+            packager.package(dir.path(), &chapters),
+            Err(MangadexError::PackageError(_))
+        ));
+        assert!(chapters[0].path().exists() && chapters[1].path().exists());
+    }
+}
@@ -0,0 +1,724 @@
+use super::comicinfo::ComicInfo;
+use super::packager::PackageChapter;
+use super::util::utc;
+use super::MangadexError;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use pdf_writer::types::Direction;
+use pdf_writer::writers::Catalog;
+use pdf_writer::writers::DocumentInfo;
+use pdf_writer::Chunk;
+use pdf_writer::Content;
+use pdf_writer::Date;
+use pdf_writer::Filter;
+use pdf_writer::Name;
+use pdf_writer::Rect;
+use pdf_writer::Ref;
+use pdf_writer::TextStr;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tracing::warn;
+
+const CATALOG_ID: Ref = Ref::new(1);
+const PAGE_TREE_ID: Ref = Ref::new(2);
+const INFO_ID: Ref = Ref::new(3);
+const OUTLINE_ID: Ref = Ref::new(4);
+const FIRST_FREE_ID: i32 = 5;
+
+const IMAGE_NAME: Name = Name(b"Im0");
+
+/// What it takes to add pages to a PDF written by [`write_pdf`], saved next to
+/// it since reading the objects back from the PDF itself would need a parser.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PdfIndex {
+    pages: Vec<i32>,
+    chapters: Vec<PdfChapter>,
+    next_id: i32,
+    xref_offset: usize,
+    /// Length of the PDF described, telling apart an index that was not
+    /// updated along with it
+    #[serde(default)]
+    size: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PdfChapter {
+    name: String,
+    label: String,
+    page: i32,
+}
+
+/// Objects appended to a PDF, with the cross-reference section that makes
+/// them the current revision.
+struct Revision {
+    buf: Vec<u8>,
+    base: usize,
+    offsets: Vec<(i32, usize)>,
+}
+
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+}
+
+/// A page image encoded the way PDF image streams expect it.
+struct PdfImage {
+    width: i32,
+    height: i32,
+    color_space: ColorSpace,
+    filter: Filter,
+    data: Vec<u8>,
+    alpha: Option<Vec<u8>>,
+}
+
+/// Index of the PDF at `pdf_path`.
+fn index_path(pdf_path: &Path) -> PathBuf {
+    let name = pdf_path.file_name().unwrap_or_default().to_string_lossy();
+    pdf_path.with_file_name(format!(".{name}.json"))
+}
+
+/// Names of the chapter folders written into the PDF at `pdf_path`.
+pub(crate) fn pdf_chapters(pdf_path: &Path) -> Result<Vec<String>, MangadexError> {
+    let index_path = index_path(pdf_path);
+    if !pdf_path.exists() || !index_path.exists() {
+        return Ok(Vec::new());
+    }
+    let index: PdfIndex = serde_json::from_slice(&fs::read(index_path)?)?;
+    Ok(index.chapters.into_iter().map(|c| c.name).collect())
+}
+
+/// Write a PDF with a page per image of `chapters`, each page the size of its
+/// image, `cover` first, and a bookmark per chapter. When `append`, chapters
+/// are added to the PDF already at `pdf_path` as an incremental update, which
+/// keeps its cover, or replace the same chapter where it was.
+///
+/// Returns the folders of the chapters with pages that could not be added.
+pub(crate) fn write_pdf(
+    pdf_path: &Path,
+    cover: Option<&Path>,
+    comic_info: &ComicInfo,
+    chapters: &[&PackageChapter],
+    append: bool,
+) -> Result<Vec<PathBuf>, MangadexError> {
+    let index_path = index_path(pdf_path);
+    let tmp_path = pdf_path.with_extension("pdf.tmp");
+    let mut index = PdfIndex {
+        next_id: FIRST_FREE_ID,
+        ..PdfIndex::default()
+    };
+    let appending = append && pdf_path.exists();
+    let mut revision = if appending {
+        if !index_path.exists() {
+            return Err(MangadexError::PackageError(format!(
+                "cannot add chapters to {}, {} is missing",
+                pdf_path.display(),
+                index_path.display()
+            )));
+        }
+        index = serde_json::from_slice(&fs::read(&index_path)?)?;
+        fs::copy(pdf_path, &tmp_path)?;
+        let size = fs::metadata(&tmp_path)?.len() as usize;
+        // Objects would be appended at the wrong offsets
+        if index.size != 0 && index.size != size {
+            fs::remove_file(&tmp_path)?;
+            return Err(MangadexError::PackageError(format!(
+                "cannot add chapters to {}, {} does not describe it",
+                pdf_path.display(),
+                index_path.display()
+            )));
+        }
+        Revision::new(size)
+    } else {
+        let mut revision = Revision::new(0);
+        revision.buf.extend(b"%PDF-1.7\n%\x80\x80\x80\x80\n\n");
+        if let Some(cover) = cover {
+            add_page(&mut revision, &mut index, fs::read(cover)?, cover);
+        }
+        revision
+    };
+
+    let mut incomplete = Vec::new();
+    for chapter in chapters {
+        // A chapter already in the PDF is replaced where it was, its previous
+        // pages are no longer in the page tree
+        let name = chapter.folder_name();
+        let previous = index.chapters.iter().position(|c| c.name == name);
+        let mut position = index.pages.len();
+        if let Some(i) = previous {
+            let page_position = |page| index.pages.iter().position(|&p| p == page);
+            let start = page_position(index.chapters[i].page).unwrap_or(index.pages.len());
+            let end = index
+                .chapters
+                .get(i + 1)
+                .and_then(|next| page_position(next.page))
+                .unwrap_or(index.pages.len())
+                .max(start);
+            index.pages.drain(start..end);
+            position = start;
+        }
+        let added = index.pages.len();
+        let mut files: Vec<PathBuf> = fs::read_dir(&chapter.path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()?;
+        files.sort();
+        let mut first_page = None;
+        for file_path in files {
+            let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+            if !file_path.is_file() || file_name.starts_with('.') {
+                continue;
+            }
+            let page = add_page(&mut revision, &mut index, fs::read(&file_path)?, &file_path);
+            if page.is_none() && !incomplete.contains(&chapter.path) {
+                incomplete.push(chapter.path.clone());
+            }
+            first_page = first_page.or(page);
+        }
+        let pages: Vec<i32> = index.pages.drain(added..).collect();
+        index.pages.splice(position..position, pages);
+        let pdf_chapter = first_page.map(|page| PdfChapter {
+            name,
+            label: chapter.label(),
+            page,
+        });
+        match (previous, pdf_chapter) {
+            (Some(i), Some(pdf_chapter)) => index.chapters[i] = pdf_chapter,
+            (Some(i), None) => {
+                index.chapters.remove(i);
+            }
+            (None, Some(pdf_chapter)) => index.chapters.push(pdf_chapter),
+            (None, None) => {}
+        }
+    }
+
+    // The catalog, page tree, outline and document information keep their ids,
+    // each revision replacing the objects of the previous one
+    let language = comic_info.language.as_deref().unwrap_or("und");
+    let right_to_left = comic_info.right_to_left;
+    revision.object(CATALOG_ID, |chunk| {
+        let mut catalog = chunk.indirect(CATALOG_ID).start::<Catalog>();
+        catalog.pages(PAGE_TREE_ID);
+        catalog.outlines(OUTLINE_ID);
+        catalog.lang(TextStr(language));
+        if right_to_left {
+            catalog.viewer_preferences().direction(Direction::R2L);
+        }
+    });
+    let pages: Vec<Ref> = index.pages.iter().map(|&id| Ref::new(id)).collect();
+    revision.object(PAGE_TREE_ID, |chunk| {
+        chunk
+            .pages(PAGE_TREE_ID)
+            .kids(pages.iter().copied())
+            .count(pages.len() as i32);
+    });
+    write_outline(&mut revision, &mut index);
+    write_info(&mut revision, comic_info);
+
+    let previous = (index.xref_offset > 0).then_some(index.xref_offset);
+    index.xref_offset = revision.write_xref(index.next_id, previous);
+    index.size = revision.base + revision.buf.len();
+    let mut file = if appending {
+        fs::OpenOptions::new().append(true).open(&tmp_path)?
+    } else {
+        fs::File::create(&tmp_path)?
+    };
+    file.write_all(&revision.buf)?;
+    file.sync_all()?;
+    drop(file);
+    // Both files are complete before either replaces the previous one
+    let tmp_index_path = index_path.with_extension("json.tmp");
+    fs::write(&tmp_index_path, serde_json::to_vec(&index)?)?;
+    fs::rename(&tmp_path, pdf_path)?;
+    fs::rename(&tmp_index_path, index_path)?;
+    Ok(incomplete)
+}
+
+/// Add a page showing `bytes`, returning its id. Images that cannot be put in
+/// a PDF are skipped.
+fn add_page(
+    revision: &mut Revision,
+    index: &mut PdfIndex,
+    bytes: Vec<u8>,
+    path: &Path,
+) -> Option<i32> {
+    let image = match PdfImage::new(bytes) {
+        Ok(image) => image,
+        Err(e) => {
+            warn!("Skip {} in pdf: {e}", path.display());
+            return None;
+        }
+    };
+    let mut alloc = || {
+        index.next_id += 1;
+        Ref::new(index.next_id - 1)
+    };
+    let page_id = alloc();
+    let content_id = alloc();
+    let image_id = alloc();
+    let mask_id = image.alpha.as_ref().map(|_| alloc());
+
+    let (width, height) = (image.width as f32, image.height as f32);
+    revision.object(page_id, |chunk| {
+        let mut page = chunk.page(page_id);
+        page.parent(PAGE_TREE_ID);
+        page.media_box(Rect::new(0.0, 0.0, width, height));
+        page.contents(content_id);
+        page.resources().x_objects().pair(IMAGE_NAME, image_id);
+    });
+    let mut content = Content::new();
+    content.save_state();
+    content.transform([width, 0.0, 0.0, height, 0.0, 0.0]);
+    content.x_object(IMAGE_NAME);
+    content.restore_state();
+    let content = content.finish();
+    revision.object(content_id, |chunk| {
+        chunk.stream(content_id, &content);
+    });
+    revision.object(image_id, |chunk| {
+        let mut xobject = chunk.image_xobject(image_id, &image.data);
+        xobject.filter(image.filter);
+        xobject.width(image.width);
+        xobject.height(image.height);
+        xobject.bits_per_component(8);
+        let color_space = xobject.color_space();
+        match image.color_space {
+            ColorSpace::Gray => color_space.device_gray(),
+            ColorSpace::Rgb => color_space.device_rgb(),
+            ColorSpace::Cmyk => color_space.device_cmyk(),
+        }
+        if let Some(mask_id) = mask_id {
+            xobject.s_mask(mask_id);
+        }
+    });
+    if let (Some(mask_id), Some(alpha)) = (mask_id, &image.alpha) {
+        revision.object(mask_id, |chunk| {
+            let mut mask = chunk.image_xobject(mask_id, alpha);
+            mask.filter(Filter::FlateDecode);
+            mask.width(image.width);
+            mask.height(image.height);
+            mask.bits_per_component(8);
+            mask.color_space().device_gray();
+        });
+    }
+    index.pages.push(page_id.get());
+    Some(page_id.get())
+}
+
+/// Bookmarks of every chapter, written anew since the last one has to point
+/// to the next.
+fn write_outline(revision: &mut Revision, index: &mut PdfIndex) {
+    let ids: Vec<Ref> = index
+        .chapters
+        .iter()
+        .map(|_| {
+            index.next_id += 1;
+            Ref::new(index.next_id - 1)
+        })
+        .collect();
+    revision.object(OUTLINE_ID, |chunk| {
+        let mut outline = chunk.outline(OUTLINE_ID);
+        if let (Some(first), Some(last)) = (ids.first(), ids.last()) {
+            outline.first(*first).last(*last);
+        }
+        outline.count(ids.len() as i32);
+    });
+    for (i, chapter) in index.chapters.iter().enumerate() {
+        revision.object(ids[i], |chunk| {
+            let mut item = chunk.outline_item(ids[i]);
+            item.parent(OUTLINE_ID).title(TextStr(&chapter.label));
+            if i > 0 {
+                item.prev(ids[i - 1]);
+            }
+            if let Some(next) = ids.get(i + 1) {
+                item.next(*next);
+            }
+            item.dest().page(Ref::new(chapter.page)).fit();
+        });
+    }
+}
+
+fn write_info(revision: &mut Revision, comic_info: &ComicInfo) {
+    let title = comic_info.book_title();
+    let mut authors = comic_info.writer.clone();
+    for artist in &comic_info.penciller {
+        if !authors.contains(artist) {
+            authors.push(artist.clone());
+        }
+    }
+    let authors = authors.join(", ");
+    let keywords = comic_info.genre.join(", ");
+    let (year, month, day, hour, minute, second) = utc(SystemTime::now());
+    let date = Date::new(year as u16)
+        .month(month as u8)
+        .day(day as u8)
+        .hour(hour as u8)
+        .minute(minute as u8)
+        .second(second as u8)
+        .utc_offset_hour(0);
+    revision.object(INFO_ID, |chunk| {
+        let mut info = chunk.indirect(INFO_ID).start::<DocumentInfo>();
+        info.title(TextStr(&title));
+        if !authors.is_empty() {
+            info.author(TextStr(&authors));
+        }
+        if let Some(summary) = &comic_info.summary {
+            info.subject(TextStr(summary));
+        }
+        if !keywords.is_empty() {
+            info.keywords(TextStr(&keywords));
+        }
+        info.modified_date(date);
+    });
+}
+
+impl Revision {
+    fn new(base: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            base,
+            offsets: Vec::new(),
+        }
+    }
+
+    fn object(&mut self, id: Ref, write: impl FnOnce(&mut Chunk)) {
+        let mut chunk = Chunk::new();
+        write(&mut chunk);
+        self.offsets.push((id.get(), self.base + self.buf.len()));
+        self.buf.extend_from_slice(chunk.as_bytes());
+    }
+
+    /// Write the cross-reference section and trailer, `size` being one past
+    /// the highest object id, returning the offset of the section.
+    fn write_xref(&mut self, size: i32, previous: Option<usize>) -> usize {
+        let xref_offset = self.base + self.buf.len();
+        let mut offsets = self.offsets.clone();
+        if previous.is_none() {
+            offsets.push((0, 0));
+        }
+        offsets.sort();
+
+        self.buf.extend(b"xref\n");
+        let mut i = 0;
+        while i < offsets.len() {
+            // Subsections of consecutive ids
+            let start = offsets[i].0;
+            let mut end = i + 1;
+            while end < offsets.len() && offsets[end].0 == offsets[end - 1].0 + 1 {
+                end += 1;
+            }
+            self.buf.extend(format!("{start} {}\n", end - i).as_bytes());
+            for &(id, offset) in &offsets[i..end] {
+                let entry = if id == 0 {
+                    String::from("0000000000 65535 f\r\n")
+                } else {
+                    format!("{offset:010} 00000 n\r\n")
+                };
+                self.buf.extend(entry.as_bytes());
+            }
+            i = end;
+        }
+
+        let mut trailer = format!(
+            "trailer\n<<\n  /Size {size}\n  /Root {} 0 R\n  /Info {} 0 R\n",
+            CATALOG_ID.get(),
+            INFO_ID.get()
+        );
+        if let Some(previous) = previous {
+            trailer.push_str(&format!("  /Prev {previous}\n"));
+        }
+        trailer.push_str(&format!(">>\nstartxref\n{xref_offset}\n%%EOF\n"));
+        self.buf.extend(trailer.as_bytes());
+        xref_offset
+    }
+}
+
+impl PdfImage {
+    fn new(bytes: Vec<u8>) -> Result<Self, String> {
+        if bytes.starts_with(&[0xFF, 0xD8]) {
+            Self::jpeg(bytes)
+        } else if bytes.starts_with(b"\x89PNG") {
+            Self::png(&bytes)
+        } else {
+            Self::decoded(&bytes)
+        }
+    }
+
+    /// Jpeg data is embedded as is, the size and number of components being
+    /// read from the start of frame segment.
+    fn jpeg(bytes: Vec<u8>) -> Result<Self, String> {
+        let mut i = 2;
+        while i + 9 < bytes.len() {
+            if bytes[i] != 0xFF {
+                break;
+            }
+            let marker = bytes[i + 1];
+            if marker == 0xFF {
+                i += 1;
+                continue;
+            }
+            if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                let height = u16::from_be_bytes([bytes[i + 5], bytes[i + 6]]);
+                let width = u16::from_be_bytes([bytes[i + 7], bytes[i + 8]]);
+                let color_space = match bytes[i + 9] {
+                    1 => ColorSpace::Gray,
+                    3 => ColorSpace::Rgb,
+                    4 => ColorSpace::Cmyk,
+                    n => return Err(format!("unsupported jpeg with {n} components")),
+                };
+                return Ok(Self {
+                    width: width.into(),
+                    height: height.into(),
+                    color_space,
+                    filter: Filter::DctDecode,
+                    data: bytes,
+                    alpha: None,
+                });
+            }
+            let length = u16::from_be_bytes([bytes[i + 2], bytes[i + 3]]) as usize;
+            i += 2 + length;
+        }
+        Err(String::from("invalid jpeg"))
+    }
+
+    /// Png pixels are decoded to 8 bit gray or rgb samples, the alpha channel
+    /// going into a separate mask.
+    fn png(bytes: &[u8]) -> Result<Self, String> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut pixels).map_err(|e| e.to_string())?;
+        pixels.truncate(frame.buffer_size());
+
+        let (color_space, channels) = match frame.color_type {
+            png::ColorType::Grayscale => (ColorSpace::Gray, 1),
+            png::ColorType::GrayscaleAlpha => (ColorSpace::Gray, 2),
+            png::ColorType::Rgb => (ColorSpace::Rgb, 3),
+            png::ColorType::Rgba => (ColorSpace::Rgb, 4),
+            png::ColorType::Indexed => return Err(String::from("unexpanded palette")),
+        };
+        Self::pixels(frame.width, frame.height, color_space, channels, pixels)
+    }
+
+    /// Other formats, such as gif and webp, are decoded to rgba pixels. Only
+    /// the first frame of an animation is kept.
+    fn decoded(bytes: &[u8]) -> Result<Self, String> {
+        let image = image::load_from_memory(bytes)
+            .map_err(|e| e.to_string())?
+            .to_rgba8();
+        let (width, height) = image.dimensions();
+        let mut pixels = image.into_raw();
+        if pixels.chunks_exact(4).all(|pixel| pixel[3] == u8::MAX) {
+            pixels = pixels
+                .chunks_exact(4)
+                .flat_map(|pixel| &pixel[..3])
+                .copied()
+                .collect();
+            return Self::pixels(width, height, ColorSpace::Rgb, 3, pixels);
+        }
+        Self::pixels(width, height, ColorSpace::Rgb, 4, pixels)
+    }
+
+    /// 8 bit samples of `channels` per pixel, the last one being alpha when
+    /// there are two or four.
+    fn pixels(
+        width: u32,
+        height: u32,
+        color_space: ColorSpace,
+        channels: usize,
+        pixels: Vec<u8>,
+    ) -> Result<Self, String> {
+        let (data, alpha) = if matches!(channels, 2 | 4) {
+            let mut color = Vec::with_capacity(pixels.len());
+            let mut alpha = Vec::with_capacity(pixels.len() / channels);
+            for pixel in pixels.chunks_exact(channels) {
+                color.extend_from_slice(&pixel[..channels - 1]);
+                alpha.push(pixel[channels - 1]);
+            }
+            (color, Some(alpha))
+        } else {
+            (pixels, None)
+        };
+        Ok(Self {
+            width: width as i32,
+            height: height as i32,
+            color_space,
+            filter: Filter::FlateDecode,
+            data: deflate(&data)?,
+            alpha: alpha.as_deref().map(deflate).transpose()?,
+        })
+    }
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).map_err(|e| e.to_string())?;
+    encoder.finish().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packager::test::chapter_with;
+    use crate::packager::PackageFormat;
+    use crate::packager::PackageMode;
+    use crate::packager::Packager;
+
+    fn png(width: u32, height: u32, color_type: png::ColorType, samples: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(samples).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    fn gif(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let pixels = vec![200; (width * height * 4) as usize];
+        image::codecs::gif::GifEncoder::new(&mut bytes)
+            .encode(&pixels, width, height, image::ColorType::Rgba8)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_pdf_image() {
+        // Start of image, then a start of frame for a 3x2 rgb image
+        let jpeg = b"\xFF\xD8\xFF\xE0\x00\x04\x00\x00\xFF\xC0\x00\x11\x08\x00\x02\x00\x03\x03";
+        let image = PdfImage::new(jpeg.to_vec()).unwrap();
+        assert_eq!((image.width, image.height), (3, 2));
+        assert!(matches!(image.color_space, ColorSpace::Rgb));
+        assert_eq!(image.data, jpeg);
+
+        let rgba = png(2, 1, png::ColorType::Rgba, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        let image = PdfImage::new(rgba).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        let inflate = |data: &[u8]| {
+            let mut decoder = flate2::read::ZlibDecoder::new(data);
+            let mut out = Vec::new();
+            std::io::Read::read_to_end(&mut decoder, &mut out).unwrap();
+            out
+        };
+        assert_eq!(inflate(&image.data), [1, 2, 3, 5, 6, 7]);
+        assert_eq!(inflate(image.alpha.as_ref().unwrap()), [4, 8]);
+
+        let image = PdfImage::new(gif(5, 7)).unwrap();
+        assert_eq!((image.width, image.height), (5, 7));
+        assert!(matches!(image.color_space, ColorSpace::Rgb));
+        assert_eq!(inflate(&image.data).len(), 5 * 7 * 3);
+        assert!(image.alpha.is_none());
+
+        assert!(PdfImage::new(b"GIF89a".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_write_pdf() {
+        let dir = tempfile::tempdir().unwrap();
+        let gray = png(4, 6, png::ColorType::Grayscale, &[0; 24]);
+        let gif = gif(4, 6);
+        let chapter = |name, broken: bool| {
+            let page_1: &[u8] = if broken { b"GIF89a" } else { &gif };
+            chapter_with(
+                dir.path(),
+                name,
+                &[
+                    ("page_0.png", &gray),
+                    ("page_1.gif", page_1),
+                    ("page_2.png", &gray),
+                ],
+            )
+        };
+        let packager = Packager::new(PackageMode::Single, "Series")
+            .format(PackageFormat::Pdf)
+            .comic_info(ComicInfo::new("Series").language("en"));
+
+        let first = [PackageChapter::new(chapter("chapter_1", false)).chapter(1)];
+        let written = packager.package(dir.path(), &first).unwrap();
+        assert_eq!(written, [dir.path().join("Series.pdf")]);
+        let pdf = fs::read(&written[0]).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.7"));
+        assert!(text.contains("/MediaBox [0 0 4 6]"));
+        assert!(text.contains("/Count 3"));
+        assert!(text.contains("/Title (Chapter 1)"));
+        assert!(text.contains("/Lang (en)"));
+        assert!(text.ends_with("%%EOF\n"));
+
+        // The broken page is left out, and only found in the folder kept
+        let second = [PackageChapter::new(chapter("chapter_2", true)).chapter(2)];
+        assert!(packager.is_packaged(dir.path(), &first[0]));
+        assert!(!packager.is_packaged(dir.path(), &second[0]));
+        packager.package(dir.path(), &second).unwrap();
+        let updated = fs::read(&written[0]).unwrap();
+        assert!(updated.starts_with(&pdf));
+        let text = String::from_utf8_lossy(&updated[pdf.len()..]);
+        assert!(text.contains("/Count 5"));
+        assert!(text.contains("/Title (Chapter 2)"));
+        let previous = String::from_utf8_lossy(&pdf);
+        let previous_xref = previous.rsplit("startxref\n").next().unwrap();
+        assert!(text.contains(&format!(
+            "/Prev {}",
+            previous_xref.trim_end_matches("\n%%EOF\n")
+        )));
+        assert!(packager.is_packaged(dir.path(), &second[0]));
+        assert!(!dir.path().join("chapter_1").exists());
+        assert!(dir.path().join("chapter_2").join("page_1.gif").exists());
+
+        // An index left behind by an interrupted write is not trusted
+        let index_path = index_path(&written[0]);
+        let index = fs::read(&index_path).unwrap();
+        let third = [PackageChapter::new(chapter("chapter_3", false)).chapter(3)];
+        packager.package(dir.path(), &third).unwrap();
+        fs::write(&index_path, index).unwrap();
+        let fourth = [PackageChapter::new(chapter("chapter_4", false)).chapter(4)];
+        let pdf = fs::read(&written[0]).unwrap();
+        assert!(packager.package(dir.path(), &fourth).is_err());
+        assert_eq!(fs::read(&written[0]).unwrap(), pdf);
+        assert!(!dir.path().join("Series.pdf.tmp").exists());
+    }
+
+    #[test]
+    fn test_write_pdf_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let gray = png(4, 6, png::ColorType::Grayscale, &[0; 24]);
+        let chapter = |name, page_1: &[u8]| {
+            chapter_with(
+                dir.path(),
+                name,
+                &[("page_0.png", &gray), ("page_1.png", page_1)],
+            )
+        };
+        let packager = Packager::new(PackageMode::Single, "Series").format(PackageFormat::Pdf);
+        let chapters = [
+            PackageChapter::new(chapter("chapter_1", b"broken")).chapter(1),
+            PackageChapter::new(chapter("chapter_2", &gray)).chapter(2),
+        ];
+        let written = packager.package(dir.path(), &chapters).unwrap();
+        assert!(chapters[0].path().exists());
+
+        // Once its page is fixed, the first chapter replaces itself before
+        // the second one
+        chapter("chapter_1", &gray);
+        packager.package(dir.path(), &chapters[..1]).unwrap();
+        assert!(!chapters[0].path().exists());
+        let pdf = fs::read(&written[0]).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text
+            .rsplit("/Type /Pages")
+            .next()
+            .unwrap()
+            .contains("/Count 4"));
+        assert_eq!(
+            pdf_chapters(&written[0]).unwrap(),
+            ["chapter_1", "chapter_2"]
+        );
+    }
+}
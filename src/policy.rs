@@ -0,0 +1,261 @@
+use super::feed::ChapterInfo;
+use super::feed::ScanlationGroup;
+use super::query::Chapter;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Tie-breaker between releases of the same chapter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preference {
+    /// Most recently published.
+    Newest,
+    /// Most pages.
+    MostPages,
+    /// Uploaded by an official publisher.
+    Official,
+}
+
+impl FromStr for Preference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "newest" => Ok(Self::Newest),
+            "most-pages" => Ok(Self::MostPages),
+            "official" => Ok(Self::Official),
+            _ => Err(format!("'{s}' is not one of: newest, most-pages, official")),
+        }
+    }
+}
+
+/// Which release to download when several groups uploaded the same chapter.
+///
+/// Releases from `preferred_group`s win in the order the groups were given,
+/// then releases from any `fallback_group`, then everything else. Releases of
+/// the same rank are compared with each [`Preference`] in turn, and the first
+/// release listed by the aggregate wins the remaining ties.
+#[derive(Debug, Clone, Default)]
+pub struct SelectionPolicy {
+    pub(crate) preferred_groups: Vec<String>,
+    pub(crate) fallback_groups: Vec<String>,
+    pub(crate) preferences: Vec<Preference>,
+    pub(crate) excluded_groups: Vec<String>,
+    pub(crate) excluded_uploaders: Vec<String>,
+}
+
+impl SelectionPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Group id or name.
+    pub fn preferred_group(mut self, group: impl ToString) -> Self {
+        self.preferred_groups.push(group.to_string());
+        self
+    }
+
+    /// Group id or name.
+    pub fn fallback_group(mut self, group: impl ToString) -> Self {
+        self.fallback_groups.push(group.to_string());
+        self
+    }
+
+    pub fn prefer(mut self, preference: Preference) -> Self {
+        self.preferences.push(preference);
+        self
+    }
+
+    /// Group id or name whose releases are never picked.
+    pub fn exclude_group(mut self, group: impl ToString) -> Self {
+        self.excluded_groups.push(group.to_string());
+        self
+    }
+
+    /// Uploader name whose releases are never picked.
+    pub fn exclude_uploader(mut self, uploader: impl ToString) -> Self {
+        self.excluded_uploaders.push(uploader.to_string());
+        self
+    }
+
+    /// Pick one release for each of `chapters` among its id and `others`,
+    /// looked up in `feed`. Chapters whose releases are all excluded, external
+    /// or missing from the feed are dropped.
+    pub fn select<'a>(
+        &self,
+        chapters: &[&Chapter],
+        feed: &'a [ChapterInfo],
+    ) -> Vec<&'a ChapterInfo> {
+        let by_id: HashMap<&str, &ChapterInfo> =
+            feed.iter().map(|c| (c.id().as_str(), c)).collect();
+        chapters
+            .iter()
+            .filter_map(|chapter| {
+                let releases = std::iter::once(chapter.id())
+                    .chain(chapter.others())
+                    .filter_map(|id| by_id.get(id.as_str()).copied());
+                self.choose(releases)
+            })
+            .collect()
+    }
+
+    /// Best of `releases`, the first one winning ties.
+    pub fn choose<'a>(
+        &self,
+        releases: impl IntoIterator<Item = &'a ChapterInfo>,
+    ) -> Option<&'a ChapterInfo> {
+        releases
+            .into_iter()
+            .filter(|r| self.is_allowed(r))
+            .reduce(|best, r| match self.compare(r, best) {
+                Ordering::Greater => r,
+                _ => best,
+            })
+    }
+
+    fn is_allowed(&self, release: &ChapterInfo) -> bool {
+        let excluded_uploader = release
+            .uploader()
+            .as_ref()
+            .is_some_and(|u| self.excluded_uploaders.contains(u));
+        !release.is_external()
+            && !excluded_uploader
+            && !release
+                .groups()
+                .iter()
+                .any(|g| matches(&self.excluded_groups, g))
+    }
+
+    /// Lower is better.
+    fn rank(&self, release: &ChapterInfo) -> usize {
+        let groups = release.groups();
+        self.preferred_groups
+            .iter()
+            .position(|p| groups.iter().any(|g| matches(std::slice::from_ref(p), g)))
+            .or_else(|| {
+                groups
+                    .iter()
+                    .any(|g| matches(&self.fallback_groups, g))
+                    .then_some(self.preferred_groups.len())
+            })
+            .unwrap_or(self.preferred_groups.len() + 1)
+    }
+
+    /// `Greater` when `a` should be picked over `b`.
+    fn compare(&self, a: &ChapterInfo, b: &ChapterInfo) -> Ordering {
+        let mut ordering = self.rank(b).cmp(&self.rank(a));
+        for preference in &self.preferences {
+            ordering = ordering.then_with(|| match preference {
+                Preference::Newest => a.published().cmp(&b.published()),
+                Preference::MostPages => a.pages().cmp(b.pages()),
+                Preference::Official => a.is_official().cmp(&b.is_official()),
+            });
+        }
+        ordering
+    }
+}
+
+fn matches(groups: &[String], group: &ScanlationGroup) -> bool {
+    groups.iter().any(|g| g == group.id() || g == group.name())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn release(id: &str, group: &str, publish_at: &str, pages: usize) -> ChapterInfo {
+        ChapterInfo::new(id, group, publish_at, pages)
+    }
+
+    #[test]
+    fn test_selection_policy() {
+        let releases = vec![
+            release("a", "Alpha", "2023-01-01T00:00:00+00:00", 20),
+            release("b", "Beta", "2023-03-01T00:00:00+00:00", 18),
+            release("c", "Gamma", "2023-02-01T00:00:00+00:00", 24),
+        ];
+        let pick = |policy: SelectionPolicy| policy.choose(&releases).map(|r| r.id().as_str());
+
+        assert_eq!(pick(SelectionPolicy::new()), Some("a"));
+        assert_eq!(
+            pick(SelectionPolicy::new().prefer(Preference::Newest)),
+            Some("b")
+        );
+        assert_eq!(
+            pick(SelectionPolicy::new().prefer(Preference::MostPages)),
+            Some("c")
+        );
+        assert_eq!(
+            pick(
+                SelectionPolicy::new()
+                    .preferred_group("Delta")
+                    .preferred_group("Gamma")
+                    .preferred_group("Beta")
+            ),
+            Some("c")
+        );
+        assert_eq!(
+            pick(
+                SelectionPolicy::new()
+                    .preferred_group("Delta")
+                    .fallback_group("Alpha")
+                    .fallback_group("Beta")
+                    .prefer(Preference::Newest)
+            ),
+            Some("b")
+        );
+        assert_eq!(
+            pick(
+                SelectionPolicy::new()
+                    .exclude_group("Alpha")
+                    .exclude_uploader("uploader of c")
+            ),
+            Some("b")
+        );
+        assert_eq!(
+            pick(
+                SelectionPolicy::new()
+                    .exclude_group("Alpha")
+                    .exclude_group("Beta")
+                    .exclude_group("Gamma")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_newest_across_offsets() {
+        // 23:00 UTC the day before, later in the string
+        let releases = vec![
+            release("a", "Alpha", "2023-03-01T08:00:00+09:00", 20),
+            release("b", "Beta", "2023-02-28T23:30:00+00:00", 20),
+        ];
+        let newest = SelectionPolicy::new()
+            .prefer(Preference::Newest)
+            .choose(&releases)
+            .map(|r| r.id().as_str());
+        assert_eq!(newest, Some("b"));
+    }
+
+    #[test]
+    fn test_select_chapters() {
+        let feed = vec![
+            release("a", "Alpha", "2023-01-01T00:00:00+00:00", 20),
+            release("b", "Beta", "2023-03-01T00:00:00+00:00", 18),
+            release("c", "Alpha", "2023-02-01T00:00:00+00:00", 24),
+        ];
+        let chapter = |json| serde_json::from_value::<Chapter>(json).unwrap();
+        let chapters = [
+            chapter(serde_json::json!({"chapter": "1", "id": "a", "count": 2, "others": ["b"]})),
+            chapter(serde_json::json!({"chapter": "2", "id": "c", "count": 1, "others": []})),
+            chapter(serde_json::json!({"chapter": "3", "id": "unknown", "count": 1, "others": []})),
+        ];
+        let chapters: Vec<&Chapter> = chapters.iter().collect();
+
+        let selected = SelectionPolicy::new()
+            .preferred_group("beta")
+            .select(&chapters, &feed);
+        let ids: Vec<&str> = selected.iter().map(|c| c.id().as_str()).collect();
+        assert_eq!(ids, ["b", "c"]);
+    }
+}
@@ -0,0 +1,50 @@
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// What a `ChapterDownloader` is doing, identified by chapter id.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    ChapterStarted {
+        id: String,
+        total_pages: usize,
+        present_pages: usize,
+    },
+    PageDone {
+        id: String,
+        page: usize,
+        done: usize,
+        total_pages: usize,
+        bytes: usize,
+    },
+    Retry {
+        id: String,
+        page: usize,
+        attempt: u32,
+        delay: Duration,
+        error: String,
+    },
+    ChapterFinished {
+        id: String,
+        success: bool,
+    },
+}
+
+pub trait ProgressListener: Send + Sync {
+    fn on_progress(&self, event: ProgressEvent);
+}
+
+impl<F> ProgressListener for F
+where
+    F: Fn(ProgressEvent) + Send + Sync,
+{
+    fn on_progress(&self, event: ProgressEvent) {
+        self(event)
+    }
+}
+
+impl ProgressListener for UnboundedSender<ProgressEvent> {
+    fn on_progress(&self, event: ProgressEvent) {
+        // Nobody listening anymore is not a reason to stop downloading
+        let _ = self.send(event);
+    }
+}
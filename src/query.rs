@@ -1,3 +1,5 @@
+use super::feed::ChapterInfo;
+use super::MangadexClient;
 use super::MangadexError;
 use getset::Getters;
 use reqwest::IntoUrl;
@@ -43,6 +45,42 @@ where
     Ok(num)
 }
 
+impl Volume {
+    /// Group feed chapters the way the aggregate endpoint does: one `Chapter`
+    /// per volume and chapter number, with the other releases in `others`.
+    /// External chapters are left out as they cannot be downloaded.
+    pub fn from_feed(chapters: &[ChapterInfo]) -> Vec<Volume> {
+        let mut volumes: HashMap<Option<String>, Volume> = HashMap::new();
+        for info in chapters.iter().filter(|c| !c.is_external()) {
+            let volume = volumes
+                .entry(info.volume().clone())
+                .or_insert_with(|| Volume {
+                    volume: info.volume_number(),
+                    count: 0,
+                    chapters: HashMap::new(),
+                });
+            volume.count += 1;
+            let key = info.chapter().clone().unwrap_or_else(|| "none".to_string());
+            match volume.chapters.get_mut(&key) {
+                Some(chapter) => {
+                    chapter.count += 1;
+                    chapter.others.push(info.id().clone());
+                }
+                None => {
+                    let chapter = Chapter {
+                        chapter: info.chapter_number(),
+                        id: info.id().clone(),
+                        count: 1,
+                        others: Vec::new(),
+                    };
+                    volume.chapters.insert(key, chapter);
+                }
+            }
+        }
+        volumes.into_values().collect()
+    }
+}
+
 impl MangaQuery {
     pub fn new(id: impl ToString) -> Self {
         Self {
@@ -57,7 +95,7 @@ impl MangaQuery {
             .clone()
             .into_url()
             .map_err(|_e| MangadexError::UrlParseError(url.to_string()))?;
-        if !url.domain().is_some_and(|x| x == "mangadex.org") {
+        if url.domain() != Some("mangadex.org") {
             return Err(MangadexError::UrlParseError(url.to_string()));
         }
         if let Some(mut segments) = url.path_segments() {
@@ -75,6 +113,10 @@ impl MangaQuery {
         }
     }
 
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
     pub fn group(mut self, group: impl ToString) -> Self {
         self.groups.push(group.to_string());
         self
@@ -85,7 +127,7 @@ impl MangaQuery {
         self
     }
 
-    pub async fn execute(self) -> Result<Vec<Volume>, MangadexError> {
+    pub async fn execute(self, client: &MangadexClient) -> Result<Vec<Volume>, MangadexError> {
         #[derive(Debug, Deserialize)]
         #[serde(untagged)]
         pub(crate) enum ResponseBody {
@@ -110,13 +152,9 @@ impl MangaQuery {
             query.push(("translatedLanguage[]", language));
         }
 
-        let bytes = reqwest::Client::builder()
-            .user_agent("mgdcli")
-            .build()?
-            .get(format!(
-                "https://api.mangadex.org/manga/{}/aggregate",
-                self.id
-            ))
+        let bytes = client
+            .get(&format!("manga/{}/aggregate", self.id))
+            .await?
             .query(&query)
             .send()
             .await?
@@ -159,24 +197,51 @@ where
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::mock::{MockResponse, MockServer};
 
     #[tokio::test]
     async fn test_manga_query() {
+        let server = MockServer::start().await;
+        server.mount_with(
+            "/manga/d7037b2a-874a-4360-8a7b-07f2899152fd/aggregate",
+            |req| {
+                if req.query_values("translatedLanguage[]") == ["fr"] {
+                    MockResponse::bytes(
+                        "application/json",
+                        include_str!("../tests/fixtures/aggregate.json"),
+                    )
+                } else {
+                    MockResponse::json(serde_json::json!({"result": "ok", "volumes": []}))
+                }
+            },
+        );
+        let client = server.client();
+
         let volumes = MangaQuery::new("d7037b2a-874a-4360-8a7b-07f2899152fd")
             .language("fr")
-            .execute()
+            .execute(&client)
             .await
             .unwrap();
-        assert!(!volumes.is_empty());
+        assert_eq!(volumes.len(), 3);
+        assert_eq!((&volumes).get_chapters().len(), 6);
 
         let volumes = MangaQuery::new("d7037b2a-874a-4360-8a7b-07f2899152fd")
             .language("xxx")
-            .execute()
+            .execute(&client)
             .await
             .unwrap();
         assert!(volumes.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_manga_query_not_found() {
+        let server = MockServer::start().await;
+        let result = MangaQuery::new("unknown").execute(&server.client()).await;
+        assert!(
+            matches!(result, Err(MangadexError::RequestError(e)) if e.status() == Some(reqwest::StatusCode::NOT_FOUND))
+        );
+    }
+
     #[test]
     fn test_url_parse() {
         assert!(MangaQuery::from_url("https://mangadex.org/title/99b8eaeb-9041-4bfd-8eb7-d72addc88eb7/the-cafe-terrace-and-its-goddesses").is_ok());
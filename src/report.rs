@@ -0,0 +1,100 @@
+use super::MangadexClient;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Url;
+use serde::Serialize;
+use std::time::Duration;
+use tracing::debug;
+
+pub const DEFAULT_REPORT_URL: &str = "https://api.mangadex.network/report";
+const REPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of one image fetch from a MangaDex@Home node, as expected by the
+/// network's report endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkReport {
+    pub url: String,
+    pub success: bool,
+    pub bytes: usize,
+    pub duration: u128,
+    pub cached: bool,
+}
+
+impl NetworkReport {
+    pub(crate) fn new(
+        url: &str,
+        success: bool,
+        bytes: usize,
+        duration: Duration,
+        cached: bool,
+    ) -> Self {
+        Self {
+            url: url.to_string(),
+            success,
+            bytes,
+            duration: duration.as_millis(),
+            cached,
+        }
+    }
+}
+
+/// Only MangaDex@Home nodes want reports, not the mangadex.org servers.
+pub(crate) fn is_reportable(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| {
+        url.host_str()
+            .is_some_and(|host| host != "mangadex.org" && !host.ends_with(".mangadex.org"))
+    })
+}
+
+/// Send `report` in the background if reporting is enabled on `client`, so a
+/// slow report endpoint never holds up the page it is about.
+pub(crate) fn spawn_report(client: &MangadexClient, report: NetworkReport) {
+    if client.report_url().is_none() || !is_reportable(&report.url) {
+        return;
+    }
+    let client = client.clone();
+    tokio::spawn(async move { send_report(&client, report).await });
+}
+
+/// Failures are logged and otherwise ignored, they must never fail a download.
+async fn send_report(client: &MangadexClient, report: NetworkReport) {
+    let Some(report_url) = client.report_url() else {
+        return;
+    };
+    let body = match serde_json::to_vec(&report) {
+        Ok(body) => body,
+        Err(e) => {
+            debug!("Cannot serialize network report: {e}");
+            return;
+        }
+    };
+    let result = client
+        .http()
+        .post(report_url)
+        .header(CONTENT_TYPE, "application/json")
+        .timeout(REPORT_TIMEOUT)
+        .body(body)
+        .send()
+        .await
+        .and_then(|x| x.error_for_status());
+    if let Err(e) = result {
+        debug!("Failed to report {}: {e}", report.url);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_reportable() {
+        assert!(is_reportable(
+            "https://abc.xyz.mangadex.network:443/token/data/hash/x1.png"
+        ));
+        assert!(is_reportable("http://127.0.0.1:8080/data/hash/x1.png"));
+        assert!(!is_reportable(
+            "https://uploads.mangadex.org/data/hash/x1.png"
+        ));
+        assert!(!is_reportable("https://mangadex.org/data/hash/x1.png"));
+        assert!(!is_reportable("not a url"));
+    }
+}
@@ -0,0 +1,175 @@
+use super::MangadexError;
+use rand::Rng;
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// How page downloads recover from transient failures.
+///
+/// Failed requests are retried with exponential backoff. After
+/// `reresolve_after` consecutive failures against the same at-home node, a
+/// fresh node is requested from `/at-home/server/{id}` before trying again.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub(crate) max_retries: u32,
+    pub(crate) base_delay: Duration,
+    pub(crate) max_delay: Duration,
+    pub(crate) jitter: bool,
+    pub(crate) reresolve_after: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            jitter: true,
+            reresolve_after: 2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail on the first error.
+    pub fn none() -> Self {
+        Self::default().max_retries(0)
+    }
+
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Number of consecutive failures on one at-home node before asking for
+    /// another one. Zero disables re-resolution.
+    pub fn reresolve_after(mut self, failures: u32) -> Self {
+        self.reresolve_after = failures;
+        self
+    }
+
+    /// Delay before retry number `attempt`, starting from 1.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let delay = exp.min(self.max_delay);
+        if self.jitter {
+            let half = delay / 2;
+            half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+        } else {
+            delay
+        }
+    }
+
+    /// Delay before retry number `attempt`, honouring the server's
+    /// `Retry-After` up to `max_delay`.
+    pub(crate) fn retry_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        match retry_after {
+            Some(delay) => delay.min(self.max_delay),
+            None => self.backoff(attempt),
+        }
+    }
+
+    pub(crate) fn should_reresolve(&self, failures: u32) -> bool {
+        self.reresolve_after > 0 && failures >= self.reresolve_after
+    }
+
+    pub(crate) fn is_retryable(error: &MangadexError) -> bool {
+        match error {
+            MangadexError::RequestError(e) => match e.status() {
+                Some(status) => status.as_u16() == 429 || status.is_server_error(),
+                None => true,
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Parse a `Retry-After` header, given either in seconds or as an HTTP-date.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    // A date in the past means right away
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(1000))
+            .jitter(false);
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(4), Duration::from_millis(800));
+        assert_eq!(policy.backoff(5), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(40), Duration::from_millis(1000));
+
+        let policy = policy.jitter(true);
+        for attempt in 1..6 {
+            let delay = policy.backoff(attempt);
+            let max = RetryPolicy::new()
+                .base_delay(Duration::from_millis(100))
+                .max_delay(Duration::from_millis(1000))
+                .jitter(false)
+                .backoff(attempt);
+            assert!(delay >= max / 2 && delay <= max);
+        }
+    }
+
+    #[test]
+    fn test_retry_after() {
+        assert_eq!(parse_retry_after("2"), Some(Duration::from_secs(2)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(120));
+        let delay = parse_retry_after(&date).unwrap();
+        assert!(delay > Duration::from_secs(110) && delay <= Duration::from_secs(120));
+        assert_eq!(parse_retry_after("soon"), None);
+
+        let policy = RetryPolicy::new().max_delay(Duration::from_secs(10));
+        assert_eq!(
+            policy.retry_delay(1, Some(Duration::from_secs(86400))),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            policy.retry_delay(1, parse_retry_after(&date)),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            policy.retry_delay(1, Some(Duration::from_secs(2))),
+            Duration::from_secs(2)
+        );
+    }
+}
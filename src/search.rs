@@ -0,0 +1,281 @@
+use super::model::Collection;
+use super::model::ContentRating;
+use super::model::Demographic;
+use super::model::Manga;
+use super::model::MangaStatus;
+use super::model::OrderBy;
+use super::model::OrderDirection;
+use super::model::Tag;
+use super::MangadexClient;
+use super::MangadexError;
+
+/// Search over `GET /manga`.
+#[derive(Debug, Clone, Default)]
+pub struct MangaSearch {
+    title: Option<String>,
+    authors: Vec<String>,
+    artists: Vec<String>,
+    included_tags: Vec<String>,
+    excluded_tags: Vec<String>,
+    status: Vec<MangaStatus>,
+    demographics: Vec<Demographic>,
+    content_ratings: Vec<ContentRating>,
+    translated_languages: Vec<String>,
+    year: Option<u32>,
+    order: Vec<(OrderBy, OrderDirection)>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+impl MangaSearch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: impl ToString) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    /// Author id.
+    pub fn author(mut self, author: impl ToString) -> Self {
+        self.authors.push(author.to_string());
+        self
+    }
+
+    /// Artist id.
+    pub fn artist(mut self, artist: impl ToString) -> Self {
+        self.artists.push(artist.to_string());
+        self
+    }
+
+    /// Tag id, see [`fetch_tags`].
+    pub fn included_tag(mut self, tag: impl ToString) -> Self {
+        self.included_tags.push(tag.to_string());
+        self
+    }
+
+    /// Tag id, see [`fetch_tags`].
+    pub fn excluded_tag(mut self, tag: impl ToString) -> Self {
+        self.excluded_tags.push(tag.to_string());
+        self
+    }
+
+    pub fn status(mut self, status: MangaStatus) -> Self {
+        self.status.push(status);
+        self
+    }
+
+    pub fn demographic(mut self, demographic: Demographic) -> Self {
+        self.demographics.push(demographic);
+        self
+    }
+
+    pub fn content_rating(mut self, content_rating: ContentRating) -> Self {
+        self.content_ratings.push(content_rating);
+        self
+    }
+
+    /// Only manga with chapters available in `language`.
+    pub fn language(mut self, language: impl ToString) -> Self {
+        self.translated_languages.push(language.to_string());
+        self
+    }
+
+    pub fn year(mut self, year: u32) -> Self {
+        self.year = Some(year);
+        self
+    }
+
+    pub fn order(mut self, by: OrderBy, direction: OrderDirection) -> Self {
+        self.order.push((by, direction));
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub(crate) fn query(&self) -> Vec<(String, String)> {
+        let mut query = Vec::new();
+        if let Some(title) = &self.title {
+            query.push(("title".to_string(), title.clone()));
+        }
+        for author in &self.authors {
+            query.push(("authors[]".to_string(), author.clone()));
+        }
+        for artist in &self.artists {
+            query.push(("artists[]".to_string(), artist.clone()));
+        }
+        for tag in &self.included_tags {
+            query.push(("includedTags[]".to_string(), tag.clone()));
+        }
+        for tag in &self.excluded_tags {
+            query.push(("excludedTags[]".to_string(), tag.clone()));
+        }
+        for status in &self.status {
+            query.push(("status[]".to_string(), status.to_string()));
+        }
+        for demographic in &self.demographics {
+            query.push((
+                "publicationDemographic[]".to_string(),
+                demographic.to_string(),
+            ));
+        }
+        for rating in &self.content_ratings {
+            query.push(("contentRating[]".to_string(), rating.to_string()));
+        }
+        for language in &self.translated_languages {
+            query.push((
+                "availableTranslatedLanguage[]".to_string(),
+                language.clone(),
+            ));
+        }
+        if let Some(year) = self.year {
+            query.push(("year".to_string(), year.to_string()));
+        }
+        for (by, direction) in &self.order {
+            query.push((format!("order[{by}]"), direction.to_string()));
+        }
+        if let Some(limit) = self.limit {
+            query.push(("limit".to_string(), limit.to_string()));
+        }
+        if let Some(offset) = self.offset {
+            query.push(("offset".to_string(), offset.to_string()));
+        }
+        query
+    }
+
+    pub async fn execute(
+        &self,
+        client: &MangadexClient,
+    ) -> Result<Collection<Manga>, MangadexError> {
+        let bytes = client
+            .get("manga")
+            .await?
+            .query(&self.query())
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+/// Every tag known to mangadex, to look up the ids used by [`MangaSearch`].
+pub async fn fetch_tags(client: &MangadexClient) -> Result<Vec<Tag>, MangadexError> {
+    let bytes = client
+        .get("manga/tag")
+        .await?
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let tags: Collection<Tag> = serde_json::from_slice(&bytes)?;
+    Ok(tags.into_data())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+
+    #[tokio::test]
+    async fn test_manga_search() {
+        let server = MockServer::start().await;
+        server.mount(
+            "/manga",
+            MockResponse::bytes(
+                "application/json",
+                include_str!("../tests/fixtures/search.json"),
+            ),
+        );
+        let client = server.client();
+
+        let result = MangaSearch::new()
+            .title("kanojo")
+            .status(MangaStatus::Ongoing)
+            .status(MangaStatus::Completed)
+            .limit(2)
+            .execute(&client)
+            .await
+            .unwrap();
+        assert_eq!(*result.total(), 5);
+        assert!(result.has_more());
+
+        let request = &server.requests()[0];
+        assert_eq!(request.query_values("title"), ["kanojo"]);
+        assert_eq!(request.query_values("status[]"), ["ongoing", "completed"]);
+
+        let manga = result.into_data();
+        assert_eq!(manga.len(), 2);
+        let attributes = manga[0].attributes();
+        assert_eq!(attributes.status(), &Some(MangaStatus::Ongoing));
+        assert_eq!(
+            attributes.publication_demographic(),
+            &Some(Demographic::Shounen)
+        );
+        assert_eq!(attributes.year(), &Some(2021));
+        let tags: Vec<&str> = attributes.tags().iter().map(Tag::name).collect();
+        assert_eq!(tags, ["Romance", "Comedy"]);
+        assert_eq!(manga[0].relationships_of("author").count(), 1);
+        assert!(manga[1].attributes().description().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_tags() {
+        let server = MockServer::start().await;
+        server.mount(
+            "/manga/tag",
+            MockResponse::bytes(
+                "application/json",
+                include_str!("../tests/fixtures/tags.json"),
+            ),
+        );
+        let tags = fetch_tags(&server.client()).await.unwrap();
+        assert_eq!(tags.len(), 3);
+        assert_eq!(tags[2].name(), "Harem");
+        assert_eq!(tags[2].attributes().group(), "theme");
+    }
+
+    #[test]
+    fn test_search_query() {
+        let query = MangaSearch::new()
+            .title("cafe")
+            .included_tag("tag-1")
+            .excluded_tag("tag-2")
+            .status(MangaStatus::Ongoing)
+            .content_rating(ContentRating::Safe)
+            .content_rating(ContentRating::Suggestive)
+            .year(2021)
+            .order(OrderBy::FollowedCount, OrderDirection::Desc)
+            .limit(5)
+            .query();
+        let query: Vec<(&str, &str)> = query
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(
+            query,
+            vec![
+                ("title", "cafe"),
+                ("includedTags[]", "tag-1"),
+                ("excludedTags[]", "tag-2"),
+                ("status[]", "ongoing"),
+                ("contentRating[]", "safe"),
+                ("contentRating[]", "suggestive"),
+                ("year", "2021"),
+                ("order[followedCount]", "desc"),
+                ("limit", "5"),
+            ]
+        );
+    }
+}
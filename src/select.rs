@@ -0,0 +1,248 @@
+use super::query::Chapter;
+use super::query::GetChapters;
+use super::query::Volume;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Chapters to download, parsed from a comma separated expression such as
+/// `1-10,15,20.5,v3-v5,latest:5,!13`.
+///
+/// A chapter is selected when it matches any of the selectors, or when there
+/// are only exclusions, and none of the selectors prefixed with `!`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    include: Vec<Selector>,
+    exclude: Vec<Selector>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selector {
+    /// `15` or `20.5`
+    Chapter(f32),
+    /// `1-10`, or `-10` and `10-` with an open end
+    ChapterRange(Option<f32>, Option<f32>),
+    /// `v3`
+    Volume(f32),
+    /// `v3-v5` or `v3-5`
+    VolumeRange(f32, f32),
+    /// `latest:5`, the chapters with the highest numbers
+    Latest(usize),
+}
+
+impl Selection {
+    pub fn include(&self) -> &[Selector] {
+        &self.include
+    }
+
+    pub fn exclude(&self) -> &[Selector] {
+        &self.exclude
+    }
+
+    /// Chapters of `volumes` selected, ordered by chapter number.
+    pub fn select<'a>(&self, volumes: &'a [Volume]) -> Vec<&'a Chapter> {
+        let volume_of: HashMap<&str, Option<f32>> = volumes
+            .iter()
+            .flat_map(|v| {
+                v.chapters()
+                    .values()
+                    .map(|c| (c.id().as_str(), *v.volume()))
+            })
+            .collect();
+        let chapters = volumes.get_chapters();
+        let mut numbers: Vec<f32> = chapters.iter().filter_map(|c| *c.chapter()).collect();
+        numbers.sort_by(|a, b| b.total_cmp(a));
+        numbers.dedup();
+
+        let matches = |selector: &Selector, chapter: &Chapter| {
+            let number = *chapter.chapter();
+            let volume = volume_of.get(chapter.id().as_str()).copied().flatten();
+            match *selector {
+                Selector::Chapter(x) => number == Some(x),
+                Selector::ChapterRange(from, to) => number.is_some_and(|n| {
+                    n >= from.unwrap_or(f32::NEG_INFINITY) && n <= to.unwrap_or(f32::INFINITY)
+                }),
+                Selector::Volume(x) => volume == Some(x),
+                Selector::VolumeRange(from, to) => volume.is_some_and(|v| v >= from && v <= to),
+                // The count may exceed the number of chapters
+                Selector::Latest(count) => {
+                    count > 0
+                        && number.is_some_and(|n| {
+                            n >= numbers.get(count - 1).copied().unwrap_or(f32::NEG_INFINITY)
+                        })
+                }
+            }
+        };
+        chapters
+            .into_iter()
+            .filter(|c| {
+                (self.include.is_empty() || self.include.iter().any(|s| matches(s, c)))
+                    && !self.exclude.iter().any(|s| matches(s, c))
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Selection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut selection = Selection {
+            include: Vec::new(),
+            exclude: Vec::new(),
+        };
+        for item in s.split(',').map(str::trim) {
+            match item.strip_prefix('!') {
+                Some(excluded) => selection.exclude.push(excluded.trim().parse()?),
+                None => selection.include.push(item.parse()?),
+            }
+        }
+        Ok(selection)
+    }
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(String::from("empty selector"));
+        }
+        if let Some(count) = s.strip_prefix("latest:") {
+            return match count.trim().parse() {
+                Ok(count) if count > 0 => Ok(Self::Latest(count)),
+                _ => Err(format!("'{count}' is not a positive number of chapters")),
+            };
+        }
+        if let Some(volumes) = s.strip_prefix(['v', 'V']) {
+            return match volumes.split_once('-') {
+                Some((from, to)) => {
+                    let from = number(from)?;
+                    let to = to.trim_start();
+                    let to = number(to.strip_prefix(['v', 'V']).unwrap_or(to))?;
+                    if from > to {
+                        return Err(format!("'{s}' ends before it starts"));
+                    }
+                    Ok(Self::VolumeRange(from, to))
+                }
+                None => Ok(Self::Volume(number(volumes)?)),
+            };
+        }
+        match s.split_once('-') {
+            Some((from, to)) => {
+                let from = (!from.trim().is_empty())
+                    .then(|| number(from))
+                    .transpose()?;
+                let to = (!to.trim().is_empty()).then(|| number(to)).transpose()?;
+                match (from, to) {
+                    (None, None) => Err(format!("'{s}' has no bounds")),
+                    (Some(from), Some(to)) if from > to => {
+                        Err(format!("'{s}' ends before it starts"))
+                    }
+                    _ => Ok(Self::ChapterRange(from, to)),
+                }
+            }
+            None => Ok(Self::Chapter(number(s)?)),
+        }
+    }
+}
+
+fn number(s: &str) -> Result<f32, String> {
+    match s.trim().parse::<f32>() {
+        Ok(x) if x.is_finite() && x >= 0.0 => Ok(x),
+        _ => Err(format!("'{}' is not a chapter or volume number", s.trim())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn volumes() -> Vec<Volume> {
+        let volume = |volume: &str, chapters: &[&str]| {
+            let chapters: serde_json::Map<String, serde_json::Value> = chapters
+                .iter()
+                .map(|&c| {
+                    let chapter = serde_json::json!({
+                        "chapter": c,
+                        "id": format!("id-{c}"),
+                        "count": 1,
+                        "others": [],
+                    });
+                    (c.to_string(), chapter)
+                })
+                .collect();
+            serde_json::json!({"volume": volume, "count": chapters.len(), "chapters": chapters})
+        };
+        serde_json::from_value(serde_json::json!([
+            volume("1", &["1", "2", "3"]),
+            volume("2", &["4", "5", "5.5"]),
+            volume("3", &["6", "7"]),
+            volume("none", &["8", "9", "none"]),
+        ]))
+        .unwrap()
+    }
+
+    fn selected(expression: &str) -> Vec<String> {
+        let selection: Selection = expression.parse().unwrap();
+        let volumes = volumes();
+        selection
+            .select(&volumes)
+            .iter()
+            .map(|c| c.id().trim_start_matches("id-").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        let selection: Selection = "1-10, 15,20.5,v3-v5,latest:5,!13".parse().unwrap();
+        assert_eq!(
+            selection.include(),
+            [
+                Selector::ChapterRange(Some(1.0), Some(10.0)),
+                Selector::Chapter(15.0),
+                Selector::Chapter(20.5),
+                Selector::VolumeRange(3.0, 5.0),
+                Selector::Latest(5),
+            ]
+        );
+        assert_eq!(selection.exclude(), [Selector::Chapter(13.0)]);
+
+        assert_eq!("v2".parse(), Ok(Selector::Volume(2.0)));
+        assert_eq!("V1-3".parse(), Ok(Selector::VolumeRange(1.0, 3.0)));
+        assert_eq!("10-".parse(), Ok(Selector::ChapterRange(Some(10.0), None)));
+        assert_eq!("-10".parse(), Ok(Selector::ChapterRange(None, Some(10.0))));
+        assert_eq!("! v2".parse::<Selection>().unwrap().exclude().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_selection_errors() {
+        for expression in [
+            "", "1,,2", "abc", "-", "10-1", "v5-v3", "vx", "latest:0", "latest:x", "1-2-3", "inf",
+            "!",
+        ] {
+            assert!(
+                expression.parse::<Selection>().is_err(),
+                "'{expression}' should not parse"
+            );
+        }
+        assert_eq!(
+            "1,x".parse::<Selection>(),
+            Err(String::from("'x' is not a chapter or volume number"))
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        assert_eq!(selected("1-3,5.5"), ["1", "2", "3", "5.5"]);
+        assert_eq!(selected("v2"), ["4", "5", "5.5"]);
+        assert_eq!(selected("v1-v2,!v1,9"), ["4", "5", "5.5", "9"]);
+        assert_eq!(selected("latest:3"), ["7", "8", "9"]);
+        assert_eq!(selected("latest:3,!8"), ["7", "9"]);
+        assert_eq!(selected("!1-8"), ["none", "9"]);
+        assert_eq!(
+            selected("7-,latest:100"),
+            ["1", "2", "3", "4", "5", "5.5", "6", "7", "8", "9"]
+        );
+        assert!(selected("20").is_empty());
+    }
+}
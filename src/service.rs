@@ -1,5 +1,21 @@
+use super::manifest::ChapterManifest;
+use super::manifest::PageEntry;
+use super::manifest::INCOMPLETE_MARKER_FILE_NAME;
+use super::report::spawn_report;
+use super::report::NetworkReport;
+use super::retry::parse_retry_after;
+use super::stream::PageSink;
+use super::MangadexClient;
 use super::MangadexError;
+use super::ProgressEvent;
+use super::ProgressListener;
+use super::RetryPolicy;
+use bytes::Bytes;
 use futures::Future;
+use futures::StreamExt;
+use getset::Getters;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::header::RETRY_AFTER;
 use reqwest::IntoUrl;
 use serde::Deserialize;
 use std::fmt::Debug;
@@ -7,19 +23,124 @@ use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tokio::sync::SemaphorePermit;
 use tower::Service;
 use tracing::debug;
 use tracing::debug_span;
 use tracing::instrument;
+use tracing::warn;
+use tracing::Instrument;
 
-#[derive(Debug)]
-pub struct ChapterDownloader;
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+#[derive(Clone, Default)]
+pub struct ChapterDownloader {
+    client: MangadexClient,
+    connections: Option<Arc<Semaphore>>,
+    progress: Option<Arc<dyn ProgressListener>>,
+}
 
 #[derive(Debug)]
 pub struct ChapterDownloadRequest {
     pub(crate) id: String,
     pub(crate) data_saver: bool,
     pub(crate) path: PathBuf,
+    pub(crate) resume: bool,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) concurrency: usize,
+    pub(crate) allow_incomplete: bool,
+    pub(crate) sink: Option<Arc<dyn PageSink>>,
+}
+
+/// What a `ChapterDownloader` did for one request.
+#[derive(Debug, Clone, Getters)]
+#[getset(get = "pub")]
+pub struct ChapterDownloadReport {
+    id: String,
+    path: PathBuf,
+    pages: Vec<PageReport>,
+    failed: Vec<PageFailure>,
+    elapsed: Duration,
+}
+
+#[derive(Debug, Clone, Getters)]
+#[getset(get = "pub")]
+pub struct PageReport {
+    index: usize,
+    path: PathBuf,
+    size: u64,
+    content_type: Option<String>,
+    /// Url the page was fetched from
+    url: String,
+    /// Base url of the at-home node that served the page
+    node: String,
+    /// Hex SHA-256 of the page
+    sha256: String,
+    retries: u32,
+    elapsed: Duration,
+    /// The page was already on disk and not fetched again
+    resumed: bool,
+}
+
+#[derive(Debug, Clone, Getters)]
+pub struct PageFailure {
+    #[getset(get = "pub")]
+    index: usize,
+    /// Page file name on the at-home node
+    #[getset(get = "pub")]
+    source: String,
+    /// Shared so that reports stay `Clone`
+    error: Arc<MangadexError>,
+}
+
+impl ChapterDownloadReport {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Number of pages in the chapter, downloaded or not.
+    pub fn total_pages(&self) -> usize {
+        self.pages.len() + self.failed.len()
+    }
+
+    /// Total size in bytes of the chapter's pages.
+    pub fn size(&self) -> u64 {
+        self.pages.iter().map(|p| p.size).sum()
+    }
+
+    /// Pages fetched by this request, excluding those already on disk.
+    pub fn fetched(&self) -> impl Iterator<Item = &PageReport> {
+        self.pages.iter().filter(|p| !p.resumed)
+    }
+}
+
+impl PageFailure {
+    pub fn error(&self) -> &MangadexError {
+        &self.error
+    }
+}
+
+impl PageReport {
+    fn from_manifest(dir: &Path, entry: &PageEntry) -> Self {
+        Self {
+            index: *entry.index(),
+            path: dir.join(entry.file()),
+            size: *entry.size(),
+            content_type: entry.content_type().clone(),
+            url: entry.url().clone(),
+            node: entry.node().clone(),
+            sha256: entry.sha256().clone(),
+            retries: 0,
+            elapsed: Duration::ZERO,
+            resumed: true,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,11 +159,10 @@ struct ChapterDownloadData {
 }
 
 impl ChapterData {
-    pub async fn new(id: &str) -> Result<Self, MangadexError> {
-        let bytes = reqwest::Client::builder()
-            .user_agent("mdgcli")
-            .build()?
-            .get(format!("https://api.mangadex.org/at-home/server/{id}"))
+    pub async fn new(client: &MangadexClient, id: &str) -> Result<Self, MangadexError> {
+        let bytes = client
+            .get(&format!("at-home/server/{id}"))
+            .await?
             .send()
             .await?
             .error_for_status()?
@@ -52,12 +172,50 @@ impl ChapterData {
     }
 }
 
+impl ChapterDownloader {
+    pub fn new(client: MangadexClient) -> Self {
+        Self {
+            client,
+            connections: None,
+            progress: None,
+        }
+    }
+
+    /// Limit the number of page requests in flight across every chapter this
+    /// downloader is working on.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.connections = Some(Arc::new(Semaphore::new(max_connections.max(1))));
+        self
+    }
+
+    /// Send progress of every chapter downloaded by this service to `listener`.
+    pub fn progress(mut self, listener: impl ProgressListener + 'static) -> Self {
+        self.progress = Some(Arc::new(listener));
+        self
+    }
+}
+
+impl Debug for ChapterDownloader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChapterDownloader")
+            .field("client", &self.client)
+            .field("connections", &self.connections)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
 impl ChapterDownloadRequest {
     pub fn new(id: &str) -> Self {
         Self {
             id: id.to_string(),
             data_saver: true,
             path: PathBuf::from("."),
+            resume: true,
+            retry: RetryPolicy::default(),
+            concurrency: DEFAULT_CONCURRENCY,
+            allow_incomplete: false,
+            sink: None,
         }
     }
 
@@ -66,7 +224,7 @@ impl ChapterDownloadRequest {
             .clone()
             .into_url()
             .map_err(|_e| MangadexError::UrlParseError(url.to_string()))?;
-        if !url.domain().is_some_and(|x| x == "mangadex.org") {
+        if url.domain() != Some("mangadex.org") {
             return Err(MangadexError::UrlParseError(url.to_string()));
         }
         if let Some(mut segments) = url.path_segments() {
@@ -93,12 +251,43 @@ impl ChapterDownloadRequest {
         self.path = path.as_ref().to_path_buf();
         self
     }
+
+    /// Skip pages already downloaded to `path` by a previous request.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Maximum number of pages of this chapter downloaded at the same time.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Succeed even when some pages could not be downloaded. The failures are
+    /// listed in the report and in a marker file next to the pages.
+    pub fn allow_incomplete(mut self, allow_incomplete: bool) -> Self {
+        self.allow_incomplete = allow_incomplete;
+        self
+    }
+
+    /// Hand pages to `sink` instead of writing them to `path`. Nothing is
+    /// resumed and no manifest is kept then.
+    pub fn sink(mut self, sink: impl PageSink + 'static) -> Self {
+        self.sink = Some(Arc::new(sink));
+        self
+    }
 }
 
 impl Service<ChapterDownloadRequest> for ChapterDownloader {
-    type Response = ();
+    type Response = ChapterDownloadReport;
     type Error = MangadexError;
-    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
 
     fn poll_ready(
         &mut self,
@@ -108,69 +297,339 @@ impl Service<ChapterDownloadRequest> for ChapterDownloader {
     }
 
     fn call(&mut self, req: ChapterDownloadRequest) -> Self::Future {
-        let span = debug_span!("chapter_downloader");
+        let span = debug_span!("chapter_downloader", id = %req.id);
+        let client = self.client.clone();
+        let connections = self.connections.clone();
+        let progress = self.progress.clone();
         let fut = async move {
-            let _enter = span.enter();
             debug!(?req);
-            let chapter_data = ChapterData::new(&req.id).await?;
-            download_chapter(&chapter_data, &req.path, req.data_saver).await?;
-            Ok(())
+            let result = async {
+                let chapter_data = ChapterData::new(&client, &req.id).await?;
+                download_chapter(
+                    &client,
+                    &chapter_data,
+                    &req,
+                    connections.as_deref(),
+                    progress.as_deref(),
+                )
+                .await
+            }
+            .await;
+            if let (Err(_), Some(sink)) = (&result, &req.sink) {
+                sink.discard();
+            }
+            if let Some(progress) = &progress {
+                progress.on_progress(ProgressEvent::ChapterFinished {
+                    id: req.id.clone(),
+                    success: result.is_ok(),
+                });
+            }
+            result
         };
 
-        Box::pin(fut)
+        Box::pin(fut.instrument(span))
     }
 }
 
-#[instrument(skip(chapter))]
+/// State shared by the pages of a chapter while it is being downloaded. The
+/// at-home node serving the pages is replaced when it keeps failing.
+struct ChapterSession<'a> {
+    client: &'a MangadexClient,
+    chapter_id: &'a str,
+    base_url: tokio::sync::Mutex<String>,
+    connections: Option<&'a Semaphore>,
+    progress: Option<&'a dyn ProgressListener>,
+    sink: Option<&'a dyn PageSink>,
+    done: AtomicUsize,
+    total: usize,
+}
+
+impl<'a> ChapterSession<'a> {
+    fn emit(&self, event: ProgressEvent) {
+        if let Some(progress) = self.progress {
+            progress.on_progress(event);
+        }
+    }
+
+    fn page_done(&self, page: usize, bytes: usize) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        self.emit(ProgressEvent::PageDone {
+            id: self.chapter_id.to_string(),
+            page,
+            done,
+            total_pages: self.total,
+            bytes,
+        });
+    }
+
+    async fn base_url(&self) -> String {
+        self.base_url.lock().await.clone()
+    }
+
+    /// Wait for a slot in the connection budget shared by all chapters.
+    async fn connection(&self) -> Option<SemaphorePermit<'a>> {
+        match self.connections {
+            Some(connections) => connections.acquire().await.ok(),
+            None => None,
+        }
+    }
+
+    async fn reresolve(&self, failed: &str) -> Result<(), MangadexError> {
+        let mut base_url = self.base_url.lock().await;
+        // Another page may already have replaced the failing node
+        if *base_url == failed {
+            let chapter = ChapterData::new(self.client, self.chapter_id).await?;
+            debug!(
+                "Replace at-home node {} with {}",
+                base_url, chapter.base_url
+            );
+            *base_url = chapter.base_url;
+        }
+        Ok(())
+    }
+}
+
+#[instrument(skip(client, chapter, connections, progress))]
 async fn download_chapter(
+    client: &MangadexClient,
     chapter: &ChapterData,
-    path: impl AsRef<Path> + Debug,
-    data_saver: bool,
-) -> Result<(), MangadexError> {
-    async fn download_one(url: String, file: PathBuf) -> Result<(), MangadexError> {
-        debug!("Download {}", file.display());
-        let bytes = reqwest::ClientBuilder::new()
-            .user_agent("mdgcli")
-            .build()
-            .unwrap()
-            .get(url)
-            .send()
-            .await?
-            .error_for_status()?
-            .bytes()
-            .await?;
-        fs::write(file, &bytes)?;
-        Ok(())
+    req: &ChapterDownloadRequest,
+    connections: Option<&Semaphore>,
+    progress: Option<&dyn ProgressListener>,
+) -> Result<ChapterDownloadReport, MangadexError> {
+    async fn fetch(
+        client: &MangadexClient,
+        url: &str,
+    ) -> Result<(Bytes, Option<String>), (MangadexError, Option<Duration>)> {
+        let start = Instant::now();
+        let mut cached = false;
+        let result = async {
+            let response = client
+                .get_url(url)
+                .send()
+                .await
+                .map_err(|e| (e.into(), None))?;
+            cached = response
+                .headers()
+                .get("x-cache")
+                .and_then(|x| x.to_str().ok())
+                .is_some_and(|x| x.starts_with("HIT"));
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|x| x.to_str().ok())
+                .and_then(parse_retry_after);
+            let response = response
+                .error_for_status()
+                .map_err(|e| (e.into(), retry_after))?;
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|x| x.to_str().ok())
+                .map(str::to_string);
+            let bytes = response.bytes().await.map_err(|e| (e.into(), None))?;
+            Ok((bytes, content_type))
+        }
+        .await;
+
+        let report = match &result {
+            Ok((bytes, _)) => NetworkReport::new(url, true, bytes.len(), start.elapsed(), cached),
+            Err(_) => NetworkReport::new(url, false, 0, start.elapsed(), cached),
+        };
+        spawn_report(client, report);
+        result
+    }
+
+    async fn download_one(
+        session: &ChapterSession<'_>,
+        policy: &RetryPolicy,
+        path: String,
+        dir: &Path,
+        index: usize,
+        file: String,
+        source: &str,
+    ) -> Result<(PageEntry, PageReport), MangadexError> {
+        debug!("Download {}", file);
+        let start = Instant::now();
+        let mut attempt = 0;
+        let mut node_failures = 0;
+        let (bytes, content_type, url, node) = loop {
+            let base_url = session.base_url().await;
+            let url = format!("{base_url}/{path}");
+            let connection = session.connection().await;
+            let result = fetch(session.client, &url).await;
+            drop(connection);
+            let error = match result {
+                Ok((bytes, content_type)) => break (bytes, content_type, url, base_url),
+                Err((e, _)) if attempt >= policy.max_retries || !RetryPolicy::is_retryable(&e) => {
+                    return Err(e)
+                }
+                Err(e) => e,
+            };
+
+            attempt += 1;
+            node_failures += 1;
+            let (e, retry_after) = error;
+            let delay = policy.retry_delay(attempt, retry_after);
+            warn!(
+                "Retry {file} in {delay:?} ({attempt}/{}): {e}",
+                policy.max_retries
+            );
+            session.emit(ProgressEvent::Retry {
+                id: session.chapter_id.to_string(),
+                page: index,
+                attempt,
+                delay,
+                error: e.to_string(),
+            });
+            tokio::time::sleep(delay).await;
+            if policy.should_reresolve(node_failures) {
+                session.reresolve(&base_url).await?;
+                node_failures = 0;
+            }
+        };
+        let file_path = match session.sink {
+            Some(sink) => sink.write_page(&file, &bytes)?,
+            None => {
+                let file_path = dir.join(&file);
+                fs::write(&file_path, &bytes)?;
+                file_path
+            }
+        };
+        session.page_done(index, bytes.len());
+        let entry = PageEntry::new(index, &file, source, &bytes).origin(
+            &url,
+            &node,
+            content_type.as_deref(),
+        );
+        let report = PageReport {
+            index,
+            path: file_path,
+            size: bytes.len() as u64,
+            content_type,
+            url,
+            node,
+            sha256: entry.sha256().clone(),
+            retries: attempt,
+            elapsed: start.elapsed(),
+            resumed: false,
+        };
+        Ok((entry, report))
     }
 
-    let path = path.as_ref();
-    fs::create_dir_all(path)?;
+    let start = Instant::now();
+    let path = req.path.as_path();
+    let sink = req.sink.as_deref();
+    if sink.is_none() {
+        fs::create_dir_all(path)?;
+    }
     let width = chapter.chapter.data.len().checked_ilog10().unwrap_or(0) + 1;
-    let mut futures = Vec::new();
-    let pages = if data_saver {
-        chapter.chapter.data_saver.iter().enumerate()
+    let (quality, pages) = if req.data_saver {
+        ("data-saver", &chapter.chapter.data_saver)
     } else {
-        chapter.chapter.data.iter().enumerate()
+        ("data", &chapter.chapter.data)
     };
-    let quality = if data_saver { "data-saver" } else { "data" };
-    for (i, x) in pages {
-        let url = format!(
-            "{}/{}/{}/{}",
-            chapter.base_url, quality, chapter.chapter.hash, x
-        );
+    let mut manifest = ChapterManifest::load(path)
+        .filter(|m| {
+            req.resume
+                && sink.is_none()
+                && m.hash() == &chapter.chapter.hash
+                && m.quality() == quality
+        })
+        .unwrap_or_else(|| {
+            ChapterManifest::new(&req.id, &chapter.chapter.hash, quality, pages.len())
+        });
+    let mut reports: Vec<PageReport> = pages
+        .iter()
+        .filter_map(|x| manifest.verified_page(path, x))
+        .map(|entry| PageReport::from_manifest(path, entry))
+        .collect();
+    let present_pages = reports.len();
+    let session = ChapterSession {
+        client,
+        chapter_id: &req.id,
+        base_url: tokio::sync::Mutex::new(chapter.base_url.clone()),
+        connections,
+        progress,
+        sink,
+        done: AtomicUsize::new(present_pages),
+        total: pages.len(),
+    };
+    session.emit(ProgressEvent::ChapterStarted {
+        id: req.id.clone(),
+        total_pages: pages.len(),
+        present_pages,
+    });
+
+    let mut futures = Vec::new();
+    for (i, x) in pages.iter().enumerate() {
+        if reports.iter().any(|p| p.index == i) {
+            debug!("Skip {x}, already downloaded");
+            continue;
+        }
         let ext = if x.contains(".png") { ".png" } else { ".jpg" };
-        futures.push(download_one(
-            url,
-            path.join(format!("page_{i:0width$}{ext}", width = width as usize)),
-        ));
-    }
-    if let Some(e) = futures::future::join_all(futures)
-        .await
-        .into_iter()
-        .find(|x| x.is_err())
-    {
-        e
+        let page = download_one(
+            &session,
+            &req.retry,
+            format!("{}/{}/{}", quality, chapter.chapter.hash, x),
+            path,
+            i,
+            format!("page_{i:0width$}{ext}", width = width as usize),
+            x,
+        );
+        futures.push(async move {
+            page.await.map_err(|error| PageFailure {
+                index: i,
+                source: x.clone(),
+                error: Arc::new(error),
+            })
+        });
+    }
+
+    let mut failed = Vec::new();
+    let mut pages = futures::stream::iter(futures).buffer_unordered(req.concurrency.max(1));
+    while let Some(page) = pages.next().await {
+        match page {
+            Ok((entry, report)) => {
+                manifest.record(entry);
+                reports.push(report);
+            }
+            Err(failure) => failed.push(failure),
+        }
+    }
+    // Record the pages that made it, even on failure, so a retry can resume
+    if sink.is_none() {
+        manifest.save(path)?;
+    }
+
+    reports.sort_by_key(|p| p.index);
+    failed.sort_by_key(|p| p.index);
+    let report = ChapterDownloadReport {
+        id: req.id.clone(),
+        path: path.to_path_buf(),
+        pages: reports,
+        failed,
+        elapsed: start.elapsed(),
+    };
+
+    let marker = path.join(INCOMPLETE_MARKER_FILE_NAME);
+    if report.is_complete() {
+        if sink.is_none() && marker.exists() {
+            fs::remove_file(marker)?;
+        }
+        Ok(report)
+    } else if req.allow_incomplete {
+        let failures: String = report
+            .failed
+            .iter()
+            .map(|p| format!("page {} ({}): {}\n", p.index, p.source, p.error))
+            .collect();
+        match sink {
+            Some(sink) => sink.incomplete(&failures),
+            None => fs::write(marker, failures)?,
+        }
+        Ok(report)
     } else {
-        Ok(())
+        Err(MangadexError::IncompleteChapter(Box::new(report)))
     }
 }
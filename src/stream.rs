@@ -0,0 +1,371 @@
+use super::comicinfo::read_notes;
+use super::comicinfo::ComicInfo;
+use super::comicinfo::PageKind;
+use super::comicinfo::COMIC_INFO_FILE_NAME;
+use super::packager::chapter_folder_name;
+use super::MangadexError;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::fs;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use zip::write::FileOptions;
+use zip::ZipArchive;
+use zip::ZipWriter;
+
+/// Where a `ChapterDownloader` puts the pages of a chapter instead of its
+/// folder.
+pub trait PageSink: Debug + Send + Sync {
+    /// Store page `file_name`, returning where it ended up.
+    fn write_page(&self, file_name: &str, bytes: &[u8]) -> Result<PathBuf, MangadexError>;
+
+    /// Called when the chapter failed, its pages should not be kept.
+    fn discard(&self) {}
+
+    /// Called when the chapter is kept with pages missing, `failures` listing
+    /// them one per line.
+    fn incomplete(&self, _failures: &str) {}
+}
+
+/// A cbz archive filled with pages as they are downloaded.
+///
+/// Entries go into a temporary file that replaces the archive on
+/// [`finish`](Self::finish), so an interrupted download leaves the previous
+/// archive untouched. Chapters added to an archive that already exists come
+/// after the ones it holds, a chapter it already holds is replaced in place.
+#[derive(Debug)]
+pub struct CbzStream {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    flat: bool,
+    state: Mutex<Option<CbzState>>,
+}
+
+struct CbzState {
+    writer: ZipWriter<fs::File>,
+    comic_info: ComicInfo,
+    /// Entry name, size and kind of every page
+    pages: Vec<(String, u64, PageKind)>,
+    next_folder: usize,
+    /// Folders of the chapters given pages by this stream
+    written: HashSet<String>,
+    discarded: HashSet<String>,
+    /// Folders of the chapters of the archive already there by chapter name,
+    /// and the name, previous and new folder of those written again
+    existing: HashMap<String, String>,
+    replacing: Vec<(String, String, String)>,
+    /// Notes of the archive already there, then the pages missing from each
+    /// chapter by folder
+    notes: Option<String>,
+    incomplete: Vec<(String, String)>,
+}
+
+impl Debug for CbzState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CbzState")
+            .field("pages", &self.pages.len())
+            .field("next_folder", &self.next_folder)
+            .finish()
+    }
+}
+
+/// The pages of one chapter of a [`CbzStream`].
+#[derive(Debug, Clone)]
+pub struct ChapterStream {
+    archive: Arc<CbzStream>,
+    /// Folder of the chapter in the archive, with a trailing `/`, or nothing
+    /// when pages go at the root
+    prefix: String,
+    name: String,
+}
+
+impl CbzStream {
+    /// Start writing `path` with `cover` first, pages at the root when `flat`
+    /// and in a folder per chapter otherwise. `comic_info` is completed with
+    /// the pages and written last.
+    pub(crate) fn create(
+        path: &Path,
+        cover: Option<&Path>,
+        comic_info: ComicInfo,
+        flat: bool,
+    ) -> Result<Self, MangadexError> {
+        let mut existing = if path.exists() && !flat {
+            Some(ZipArchive::new(fs::File::open(path)?)?)
+        } else {
+            None
+        };
+        let cover = cover.filter(|_| existing.is_none());
+
+        // A zip entry cannot be replaced in place, so the archive is rebuilt with
+        // the previous entries copied over and a new ComicInfo.xml
+        let tmp_path = path.with_extension("cbz.tmp");
+        let mut writer = ZipWriter::new(fs::File::create(&tmp_path)?);
+        let mut pages = Vec::new();
+        let mut roots = HashSet::new();
+        let mut folders = HashMap::new();
+        let mut notes = None;
+        if let Some(archive) = existing.as_mut() {
+            let mut entries = Vec::new();
+            for i in 0..archive.len() {
+                let name = archive.by_index_raw(i)?.name().to_string();
+                if name != COMIC_INFO_FILE_NAME {
+                    entries.push((name, i));
+                }
+            }
+            if let Ok(mut file) = archive.by_name(COMIC_INFO_FILE_NAME) {
+                let mut xml = String::new();
+                file.read_to_string(&mut xml)?;
+                notes = read_notes(&xml);
+            }
+            entries.sort();
+            for (name, i) in entries {
+                let file = archive.by_index_raw(i)?;
+                let kind = match name.split_once('/') {
+                    Some((root, _)) => {
+                        if let Some(chapter_name) = chapter_folder_name(root) {
+                            folders.insert(chapter_name.to_string(), format!("{root}/"));
+                        }
+                        roots.insert(root.to_string());
+                        PageKind::Story
+                    }
+                    None => {
+                        roots.insert(name.clone());
+                        PageKind::FrontCover
+                    }
+                };
+                pages.push((name, file.size(), kind));
+                writer.raw_copy_file(file)?;
+            }
+        }
+        if let Some(cover) = cover {
+            let extension = cover.extension().unwrap_or_default().to_string_lossy();
+            let bytes = fs::read(cover)?;
+            let name = format!("{:05}_cover.{extension}", 0);
+            writer.start_file(&name, FileOptions::default())?;
+            writer.write_all(&bytes)?;
+            pages.push((name, bytes.len() as u64, PageKind::FrontCover));
+            roots.insert(String::from("cover"));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            tmp_path,
+            flat,
+            state: Mutex::new(Some(CbzState {
+                writer,
+                comic_info,
+                pages,
+                next_folder: roots.len(),
+                written: HashSet::new(),
+                discarded: HashSet::new(),
+                existing: folders,
+                replacing: Vec::new(),
+                notes,
+                incomplete: Vec::new(),
+            })),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Receiver of the pages of chapter `name`. Chapters are ordered in the
+    /// archive by the time this is called, except those replaced.
+    pub fn chapter(self: &Arc<Self>, name: &str) -> ChapterStream {
+        let prefix = if self.flat {
+            String::new()
+        } else {
+            let mut state = self.state.lock().unwrap();
+            let index = state.as_ref().map_or(0, |s| s.next_folder);
+            let prefix = format!("{index:05}_{name}/");
+            if let Some(state) = state.as_mut() {
+                state.next_folder += 1;
+                // The pages go in a folder of their own until the chapter is
+                // known to be kept, then take the place of the previous ones
+                if let Some(previous) = state.existing.get(name) {
+                    state
+                        .replacing
+                        .push((name.to_string(), previous.clone(), prefix.clone()));
+                }
+            }
+            prefix
+        };
+        ChapterStream {
+            archive: self.clone(),
+            prefix,
+            name: name.to_string(),
+        }
+    }
+
+    fn write(&self, prefix: &str, file_name: &str, bytes: &[u8]) -> Result<PathBuf, MangadexError> {
+        let mut state = self.state.lock().unwrap();
+        let Some(state) = state.as_mut() else {
+            return Err(MangadexError::PackageError(format!(
+                "{} is already finished",
+                self.path.display()
+            )));
+        };
+        let name = format!("{prefix}{file_name}");
+        state.writer.start_file(&name, FileOptions::default())?;
+        state.writer.write_all(bytes)?;
+        state
+            .pages
+            .push((name.clone(), bytes.len() as u64, PageKind::Story));
+        state.written.insert(prefix.to_string());
+        Ok(self.path.join(name))
+    }
+
+    fn discard(&self, prefix: &str) {
+        if let Some(state) = self.state.lock().unwrap().as_mut() {
+            state.discarded.insert(prefix.to_string());
+        }
+    }
+
+    fn incomplete(&self, prefix: &str, name: &str, failures: &str) {
+        if let Some(state) = self.state.lock().unwrap().as_mut() {
+            let failures = failures.trim_end().replace('\n', "\n  ");
+            state.incomplete.push((
+                prefix.to_string(),
+                format!("{}\n  {failures}", missing_pages_note(name)),
+            ));
+        }
+    }
+
+    /// Write `ComicInfo.xml` and move the archive in place. Nothing is written
+    /// when no chapter was kept, `None` is returned then.
+    pub fn finish(&self) -> Result<Option<PathBuf>, MangadexError> {
+        let Some(state) = self.state.lock().unwrap().take() else {
+            return Ok(None);
+        };
+        let CbzState {
+            mut writer,
+            mut comic_info,
+            mut pages,
+            written,
+            discarded,
+            replacing,
+            notes,
+            incomplete,
+            ..
+        } = state;
+        if written.iter().all(|prefix| discarded.contains(prefix)) {
+            drop(writer);
+            fs::remove_file(&self.tmp_path)?;
+            return Ok(None);
+        }
+
+        let replaced: Vec<(String, String, String)> = replacing
+            .into_iter()
+            .filter(|(_, _, prefix)| written.contains(prefix) && !discarded.contains(prefix))
+            .collect();
+        let is_discarded = |name: &str| {
+            discarded
+                .iter()
+                .chain(replaced.iter().map(|(_, previous, _)| previous))
+                .any(|prefix| !prefix.is_empty() && name.starts_with(prefix.as_str()))
+        };
+        let renamed = |name: &str| {
+            replaced.iter().find_map(|(_, previous, prefix)| {
+                Some(format!("{previous}{}", name.strip_prefix(prefix.as_str())?))
+            })
+        };
+        let mut archive_path = self.tmp_path.clone();
+        if !discarded.is_empty() || !replaced.is_empty() {
+            // Entries cannot be removed from a zip, those of failed chapters
+            // and replaced ones are left behind by copying the others to a new
+            // file
+            writer.finish()?;
+            let mut archive = ZipArchive::new(fs::File::open(&self.tmp_path)?)?;
+            archive_path = self.path.with_extension("cbz.tmp2");
+            writer = ZipWriter::new(fs::File::create(&archive_path)?);
+            for i in 0..archive.len() {
+                let file = archive.by_index_raw(i)?;
+                if let Some(name) = renamed(file.name()) {
+                    writer.raw_copy_file_rename(file, name)?;
+                } else if !is_discarded(file.name()) {
+                    writer.raw_copy_file(file)?;
+                }
+            }
+            drop(archive);
+            fs::remove_file(&self.tmp_path)?;
+            pages.retain(|(name, _, _)| !is_discarded(name));
+            for (name, _, _) in &mut pages {
+                if let Some(previous) = renamed(name) {
+                    *name = previous;
+                }
+            }
+        }
+
+        // Chapters kept with missing pages are told apart in the notes, those
+        // of the chapters replaced go with them
+        let notes: Vec<String> = notes
+            .iter()
+            .flat_map(|notes| note_blocks(notes))
+            .filter(|note| {
+                !replaced
+                    .iter()
+                    .any(|(name, _, _)| note.starts_with(&missing_pages_note(name)))
+            })
+            .chain(
+                incomplete
+                    .into_iter()
+                    .filter(|(prefix, _)| !discarded.contains(prefix))
+                    .map(|(_, note)| note),
+            )
+            .collect();
+        if !notes.is_empty() {
+            comic_info = comic_info.notes(notes.join("\n"));
+        }
+
+        // Pages are listed in ComicInfo.xml in the order readers show them
+        pages.sort_by(|a, b| a.0.cmp(&b.0));
+        for (_, size, kind) in pages {
+            comic_info = comic_info.page(size, kind);
+        }
+        writer.start_file(COMIC_INFO_FILE_NAME, FileOptions::default())?;
+        writer.write_all(comic_info.to_xml().as_bytes())?;
+        writer.finish()?;
+        drop(writer);
+        fs::rename(&archive_path, &self.path)?;
+        Ok(Some(self.path.clone()))
+    }
+}
+
+/// First line of the note listing the pages missing from chapter `name`.
+fn missing_pages_note(name: &str) -> String {
+    format!("{name} is missing pages:")
+}
+
+/// Notes split by chapter, the pages of each indented under it.
+fn note_blocks(notes: &str) -> Vec<String> {
+    let mut blocks: Vec<String> = Vec::new();
+    for line in notes.lines() {
+        match blocks.last_mut() {
+            Some(block) if line.starts_with(' ') => {
+                block.push('\n');
+                block.push_str(line);
+            }
+            _ => blocks.push(line.to_string()),
+        }
+    }
+    blocks
+}
+
+impl PageSink for ChapterStream {
+    fn write_page(&self, file_name: &str, bytes: &[u8]) -> Result<PathBuf, MangadexError> {
+        self.archive.write(&self.prefix, file_name, bytes)
+    }
+
+    fn discard(&self) {
+        self.archive.discard(&self.prefix);
+    }
+
+    fn incomplete(&self, failures: &str) {
+        self.archive.incomplete(&self.prefix, &self.name, failures);
+    }
+}
@@ -1,44 +1,929 @@
-use mangadex;
+use mangadex::mock::{MockResponse, MockServer};
+use mangadex::{
+    ChapterDownloadReport, ChapterDownloadRequest, ChapterDownloader, ChapterManifest,
+    HistoryEntry, MangadexClient, MangadexError, PackageChapter, PackageMode, Packager,
+    PageFailure, ProgressEvent, RetryPolicy, INCOMPLETE_MARKER_FILE_NAME,
+};
+use std::io::Read;
 use std::time::{self, Duration};
 use tower::{Service, ServiceBuilder, ServiceExt};
 
+const HASH: &str = "3f1c1e7a9d5b4c2e8f6a0b1d2c3e4f5a";
+
+fn init_tracing() {
+    let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+}
+
+fn sample_pages(count: usize) -> Vec<(String, Vec<u8>)> {
+    (0..count)
+        .map(|i| {
+            let ext = if i % 2 == 0 { "jpg" } else { "png" };
+            (
+                format!("{}-{i:04x}.{ext}", ext.to_uppercase()),
+                vec![i as u8; 64 + i],
+            )
+        })
+        .collect()
+}
+
+fn mount_chapter(server: &MockServer, id: &str, pages: &[(String, Vec<u8>)]) {
+    let pages: Vec<(&str, &[u8])> = pages
+        .iter()
+        .map(|(name, bytes)| (name.as_str(), bytes.as_slice()))
+        .collect();
+    server.mount_chapter(id, HASH, &pages);
+}
+
+async fn download(
+    client: MangadexClient,
+    req: ChapterDownloadRequest,
+) -> Result<ChapterDownloadReport, MangadexError> {
+    ChapterDownloader::new(client)
+        .ready()
+        .await?
+        .call(req)
+        .await
+}
+
+fn failed_pages(result: &Result<ChapterDownloadReport, MangadexError>) -> &[PageFailure] {
+    match result {
+        Err(MangadexError::IncompleteChapter(report)) => report.failed(),
+        other => panic!("expected an incomplete chapter, got {other:?}"),
+    }
+}
+
 #[tokio::test]
 async fn test_limit_download_speed() {
-    tracing_subscriber::fmt::init();
-    let tmpdir = tempfile::tempdir().unwrap();
-    let mut downloader = ServiceBuilder::new()
-        .rate_limit(1, Duration::from_secs(5))
-        .service(mangadex::ChapterDownloader);
+    init_tracing();
+    let server = MockServer::start().await;
     let ids = vec![
         "e5c1c16c-ec06-47d1-970c-b71499d48833",
         "dbe91557-6bb6-4fe9-a17c-7941313847f9",
     ];
+    for id in &ids {
+        mount_chapter(&server, id, &sample_pages(3));
+    }
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let mut downloader = ServiceBuilder::new()
+        .rate_limit(1, Duration::from_secs(1))
+        .service(ChapterDownloader::new(server.client()));
 
     let clock = time::Instant::now();
     for id in ids {
-        let req = mangadex::ChapterDownloadRequest::new(id)
+        let req = ChapterDownloadRequest::new(id)
             .data_saver(true)
             .path(tmpdir.path().join(id));
         downloader.ready().await.unwrap().call(req).await.unwrap();
     }
-    assert!(clock.elapsed() > Duration::from_secs(5));
+    assert!(clock.elapsed() > Duration::from_secs(1));
 }
 
 #[tokio::test]
 async fn test_chapter_download_service() {
-    tracing_subscriber::fmt::init();
+    init_tracing();
+    let server = MockServer::start().await;
+    let pages = sample_pages(12);
+    mount_chapter(&server, "af456519-3791-47c3-af8a-23ed894b5dd8", &pages);
+
     let tmpdir = tempfile::tempdir().unwrap();
-    let mut downloader = mangadex::ChapterDownloader;
-    let req = mangadex::ChapterDownloadRequest::new("af456519-3791-47c3-af8a-23ed894b5dd8")
-        .data_saver(true)
+    let req = ChapterDownloadRequest::new("af456519-3791-47c3-af8a-23ed894b5dd8")
+        .data_saver(false)
         .path(tmpdir.path());
-    downloader
-        .ready()
+    download(server.client(), req).await.expect("Some error");
+
+    assert_eq!(
+        std::fs::read(tmpdir.path().join("page_00.jpg")).unwrap(),
+        pages[0].1
+    );
+    assert_eq!(
+        std::fs::read(tmpdir.path().join("page_11.png")).unwrap(),
+        pages[11].1
+    );
+    assert_eq!(server.hits(&format!("/data/{HASH}/{}", pages[5].0)), 1);
+    assert_eq!(
+        server.hits(&format!("/data-saver/{HASH}/{}", pages[5].0)),
+        0
+    );
+}
+
+#[tokio::test]
+async fn test_chapter_not_found() {
+    let server = MockServer::start().await;
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = ChapterDownloadRequest::new("missing").path(tmpdir.path());
+    let result = download(server.client(), req).await;
+    assert!(matches!(result, Err(MangadexError::RequestError(_))));
+}
+
+#[tokio::test]
+async fn test_page_not_found() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(4);
+    mount_chapter(&server, "chapter", &pages);
+    server.mount(
+        &format!("/data-saver/{HASH}/{}", pages[2].0),
+        MockResponse::not_found(),
+    );
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = ChapterDownloadRequest::new("chapter").path(tmpdir.path());
+    let result = download(server.client(), req).await;
+    let failed = failed_pages(&result);
+    assert_eq!(failed.len(), 1);
+    assert_eq!(*failed[0].index(), 2);
+    assert_eq!(failed[0].source(), &pages[2].0);
+    assert!(
+        matches!(failed[0].error(), MangadexError::RequestError(e) if e.status().is_some_and(|s| s == 404))
+    );
+}
+
+#[tokio::test]
+async fn test_page_rate_limited() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(4);
+    mount_chapter(&server, "chapter", &pages);
+    let page_path = format!("/data-saver/{HASH}/{}", pages[1].0);
+    server.mount_sequence(
+        &page_path,
+        vec![
+            MockResponse::too_many_requests(1),
+            MockResponse::bytes("image/png", pages[1].1.clone()),
+        ],
+    );
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .retry(RetryPolicy::new().base_delay(Duration::from_millis(1)));
+    let clock = time::Instant::now();
+    download(server.client(), req).await.unwrap();
+    assert!(clock.elapsed() >= Duration::from_secs(1));
+    assert_eq!(server.hits(&page_path), 2);
+
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .resume(false)
+        .retry(RetryPolicy::none());
+    server.mount(&page_path, MockResponse::too_many_requests(1));
+    let result = download(server.client(), req).await;
+    assert!(
+        matches!(failed_pages(&result)[0].error(), MangadexError::RequestError(e) if e.status().is_some_and(|s| s == 429))
+    );
+}
+
+#[tokio::test]
+async fn test_page_retry_after_capped() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(2);
+    mount_chapter(&server, "chapter", &pages);
+    let page_path = format!("/data-saver/{HASH}/{}", pages[0].0);
+    server.mount_sequence(
+        &page_path,
+        vec![
+            MockResponse::too_many_requests(86400),
+            MockResponse::bytes("image/png", pages[0].1.clone()),
+        ],
+    );
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .retry(RetryPolicy::new().max_delay(Duration::from_millis(50)));
+    let clock = time::Instant::now();
+    download(server.client(), req).await.unwrap();
+    assert!(clock.elapsed() < Duration::from_secs(5));
+    assert_eq!(server.hits(&page_path), 2);
+}
+
+#[tokio::test]
+async fn test_page_retry_gives_up() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(2);
+    mount_chapter(&server, "chapter", &pages);
+    let page_path = format!("/data-saver/{HASH}/{}", pages[0].0);
+    server.mount(&page_path, MockResponse::new(500));
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .retry(
+            RetryPolicy::new()
+                .max_retries(2)
+                .base_delay(Duration::from_millis(1))
+                .reresolve_after(0),
+        );
+    let result = download(server.client(), req).await;
+    assert!(
+        matches!(failed_pages(&result)[0].error(), MangadexError::RequestError(e) if e.status().is_some_and(|s| s == 500))
+    );
+    assert_eq!(server.hits(&page_path), 3);
+    assert_eq!(server.hits("/at-home/server/chapter"), 1);
+}
+
+#[tokio::test]
+async fn test_reresolve_at_home_node() {
+    let server = MockServer::start().await;
+    let broken_node = MockServer::start().await;
+    let pages = sample_pages(3);
+    mount_chapter(&server, "chapter", &pages);
+    for (name, _) in &pages {
+        broken_node.mount(
+            &format!("/data-saver/{HASH}/{name}"),
+            MockResponse::new(503),
+        );
+    }
+    let at_home = |base_url: &str| {
+        MockResponse::json(serde_json::json!({
+            "result": "ok",
+            "baseUrl": base_url,
+            "chapter": {
+                "hash": HASH,
+                "data": pages.iter().map(|p| &p.0).collect::<Vec<_>>(),
+                "dataSaver": pages.iter().map(|p| &p.0).collect::<Vec<_>>(),
+            }
+        }))
+    };
+    server.mount_sequence(
+        "/at-home/server/chapter",
+        vec![at_home(broken_node.url()), at_home(server.url())],
+    );
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .retry(
+            RetryPolicy::new()
+                .base_delay(Duration::from_millis(1))
+                .reresolve_after(2),
+        );
+    download(server.client(), req).await.unwrap();
+
+    assert!(ChapterManifest::is_complete(tmpdir.path()));
+    assert_eq!(server.hits("/at-home/server/chapter"), 2);
+    for (name, _) in &pages {
+        assert_eq!(broken_node.hits(&format!("/data-saver/{HASH}/{name}")), 2);
+        assert_eq!(server.hits(&format!("/data-saver/{HASH}/{name}")), 1);
+    }
+}
+
+#[tokio::test]
+async fn test_page_truncated() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(4);
+    mount_chapter(&server, "chapter", &pages);
+    server.mount(
+        &format!("/data-saver/{HASH}/{}", pages[3].0),
+        MockResponse::bytes("image/png", pages[3].1.clone()).truncated(10),
+    );
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .retry(RetryPolicy::none());
+    let result = download(server.client(), req).await;
+    assert!(matches!(
+        failed_pages(&result)[0].error(),
+        MangadexError::RequestError(_)
+    ));
+
+    server.mount_sequence(
+        &format!("/data-saver/{HASH}/{}", pages[3].0),
+        vec![
+            MockResponse::bytes("image/png", pages[3].1.clone()).truncated(10),
+            MockResponse::bytes("image/png", pages[3].1.clone()),
+        ],
+    );
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .retry(RetryPolicy::new().base_delay(Duration::from_millis(1)));
+    download(server.client(), req).await.unwrap();
+    assert_eq!(
+        std::fs::read(tmpdir.path().join("page_3.png")).unwrap(),
+        pages[3].1
+    );
+}
+
+#[tokio::test]
+async fn test_page_slow_response() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(2);
+    mount_chapter(&server, "chapter", &pages);
+    server.mount(
+        &format!("/data-saver/{HASH}/{}", pages[0].0),
+        MockResponse::bytes("image/jpeg", pages[0].1.clone()).delay(Duration::from_secs(2)),
+    );
+
+    let client = MangadexClient::builder()
+        .base_url(server.url())
+        .timeout(Duration::from_millis(300))
+        .build()
+        .unwrap();
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .retry(RetryPolicy::none());
+    let result = download(client, req).await;
+    assert!(
+        matches!(failed_pages(&result)[0].error(), MangadexError::RequestError(e) if e.is_timeout())
+    );
+}
+
+#[tokio::test]
+async fn test_resume_download() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(4);
+    mount_chapter(&server, "chapter", &pages);
+    server.mount(
+        &format!("/data-saver/{HASH}/{}", pages[2].0),
+        MockResponse::not_found(),
+    );
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = || ChapterDownloadRequest::new("chapter").path(tmpdir.path());
+    assert!(download(server.client(), req()).await.is_err());
+    assert!(!ChapterManifest::is_complete(tmpdir.path()));
+
+    // Bring the missing page back and corrupt one of the downloaded pages
+    mount_chapter(&server, "chapter", &pages);
+    std::fs::write(tmpdir.path().join("page_1.png"), b"garbage").unwrap();
+    let report = download(server.client(), req()).await.unwrap();
+    let resumed: Vec<bool> = report.pages().iter().map(|p| *p.resumed()).collect();
+    assert_eq!(resumed, vec![true, false, false, true]);
+    assert_eq!(report.fetched().count(), 2);
+
+    assert!(ChapterManifest::is_complete(tmpdir.path()));
+    let hits = |i: usize| server.hits(&format!("/data-saver/{HASH}/{}", pages[i].0));
+    assert_eq!(hits(0), 1);
+    assert_eq!(hits(1), 2);
+    assert_eq!(hits(2), 2);
+    assert_eq!(hits(3), 1);
+    assert_eq!(
+        std::fs::read(tmpdir.path().join("page_1.png")).unwrap(),
+        pages[1].1
+    );
+
+    download(server.client(), req().resume(false))
         .await
-        .unwrap()
-        .call(req)
+        .unwrap();
+    assert_eq!(hits(0), 2);
+}
+
+#[tokio::test]
+async fn test_network_report() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(3);
+    mount_chapter(&server, "chapter", &pages);
+    server.mount(
+        &format!("/data-saver/{HASH}/{}", pages[0].0),
+        MockResponse::bytes("image/jpeg", pages[0].1.clone()).header("x-cache", "HIT"),
+    );
+    server.mount(
+        &format!("/data-saver/{HASH}/{}", pages[2].0),
+        MockResponse::not_found(),
+    );
+
+    // The report endpoint is slow, pages must not wait for it
+    server.mount(
+        "/report",
+        MockResponse::new(200).delay(Duration::from_secs(3)),
+    );
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = ChapterDownloadRequest::new("chapter").path(tmpdir.path());
+    let clock = time::Instant::now();
+    assert!(download(server.client(), req).await.is_err());
+    assert!(clock.elapsed() < Duration::from_secs(2));
+
+    // Reports are sent in the background
+    let reports = || -> Vec<serde_json::Value> {
+        server
+            .requests()
+            .into_iter()
+            .filter(|r| r.path() == "/report")
+            .map(|r| serde_json::from_slice(r.body()).unwrap())
+            .collect()
+    };
+    while reports().len() < 3 && clock.elapsed() < Duration::from_secs(5) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let reports = reports();
+    assert_eq!(reports.len(), 3);
+    let report = |i: usize| {
+        let url = format!("{}/data-saver/{HASH}/{}", server.url(), pages[i].0);
+        reports.iter().find(|r| r["url"] == url.as_str()).unwrap()
+    };
+    assert_eq!(report(0)["success"], true);
+    assert_eq!(report(0)["bytes"], pages[0].1.len());
+    assert_eq!(report(0)["cached"], true);
+    assert_eq!(report(1)["success"], true);
+    assert_eq!(report(1)["cached"], false);
+    assert_eq!(report(2)["success"], false);
+    assert_eq!(report(2)["bytes"], 0);
+    assert!(report(2)["duration"].is_u64());
+
+    let client = MangadexClient::builder()
+        .base_url(server.url())
+        .disable_reporting()
+        .build()
+        .unwrap();
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .resume(false);
+    assert!(download(client, req).await.is_err());
+    assert_eq!(server.hits("/report"), 3);
+}
+
+#[tokio::test]
+async fn test_page_concurrency() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(6);
+    mount_chapter(&server, "chapter", &pages);
+    mount_chapter(&server, "other", &pages);
+    // Pages are held until the limit is reached, so a limit that is honoured
+    // is always reached
+    let hold_pages = |in_flight: usize| {
+        for (name, bytes) in &pages {
+            server.mount(
+                &format!("/data-saver/{HASH}/{name}"),
+                MockResponse::bytes("image/jpeg", bytes.clone())
+                    .hold_until(in_flight)
+                    .delay(Duration::from_millis(20)),
+            );
+        }
+    };
+    hold_pages(2);
+    let client = MangadexClient::builder()
+        .base_url(server.url())
+        .disable_reporting()
+        .build()
+        .unwrap();
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .concurrency(2);
+    download(client.clone(), req).await.unwrap();
+    assert!(server.max_in_flight() <= 2);
+    assert_eq!(server.max_in_flight(), 2);
+
+    hold_pages(3);
+
+    // Two chapters at once still share a single connection budget
+    let downloader = ChapterDownloader::new(client).max_connections(3);
+    let mut downloads = downloader.call_all(futures::stream::iter([
+        ChapterDownloadRequest::new("chapter")
+            .path(tmpdir.path().join("a"))
+            .concurrency(4),
+        ChapterDownloadRequest::new("other")
+            .path(tmpdir.path().join("b"))
+            .concurrency(4),
+    ]));
+    while let Some(result) = futures::StreamExt::next(&mut downloads).await {
+        result.unwrap();
+    }
+    assert!(server.max_in_flight() <= 3);
+    assert_eq!(server.max_in_flight(), 3);
+    assert!(ChapterManifest::is_complete(tmpdir.path().join("a")));
+    assert!(ChapterManifest::is_complete(tmpdir.path().join("b")));
+}
+
+#[tokio::test]
+async fn test_progress_events() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(3);
+    mount_chapter(&server, "chapter", &pages);
+    server.mount_sequence(
+        &format!("/data-saver/{HASH}/{}", pages[1].0),
+        vec![
+            MockResponse::new(503),
+            MockResponse::bytes("image/png", pages[1].1.clone()),
+        ],
+    );
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut downloader = ChapterDownloader::new(server.client()).progress(tx);
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .concurrency(1)
+        .retry(RetryPolicy::new().base_delay(Duration::from_millis(1)));
+    downloader.ready().await.unwrap().call(req).await.unwrap();
+    drop(downloader);
+
+    let mut events = Vec::new();
+    while let Some(event) = rx.recv().await {
+        events.push(event);
+    }
+    assert_eq!(events.len(), 6);
+    assert_eq!(
+        events[0],
+        ProgressEvent::ChapterStarted {
+            id: "chapter".to_string(),
+            total_pages: 3,
+            present_pages: 0
+        }
+    );
+    assert!(matches!(
+        &events[2],
+        ProgressEvent::Retry {
+            page: 1,
+            attempt: 1,
+            ..
+        }
+    ));
+    let done: Vec<(usize, usize, usize)> = events
+        .iter()
+        .filter_map(|e| match e {
+            ProgressEvent::PageDone {
+                page, done, bytes, ..
+            } => Some((*page, *done, *bytes)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(done, vec![(0, 1, 64), (1, 2, 65), (2, 3, 66)]);
+    assert_eq!(
+        events[5],
+        ProgressEvent::ChapterFinished {
+            id: "chapter".to_string(),
+            success: true
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_download_report() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(3);
+    mount_chapter(&server, "chapter", &pages);
+    server.mount_sequence(
+        &format!("/data/{HASH}/{}", pages[2].0),
+        vec![
+            MockResponse::new(500),
+            MockResponse::bytes("image/jpeg", pages[2].1.clone()),
+        ],
+    );
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .data_saver(false)
+        .retry(RetryPolicy::new().base_delay(Duration::from_millis(1)));
+    let report = download(server.client(), req).await.unwrap();
+
+    assert_eq!(report.id(), "chapter");
+    assert_eq!(report.path(), tmpdir.path());
+    assert_eq!(report.pages().len(), 3);
+    assert_eq!(report.size(), 64 + 65 + 66);
+    for (i, page) in report.pages().iter().enumerate() {
+        assert_eq!(*page.index(), i);
+        assert_eq!(*page.size(), pages[i].1.len() as u64);
+        assert_eq!(std::fs::read(page.path()).unwrap(), pages[i].1);
+        assert_eq!(page.node(), server.url());
+        assert_eq!(
+            page.url(),
+            &format!("{}/data/{HASH}/{}", server.url(), pages[i].0)
+        );
+        assert!(!page.resumed());
+    }
+    assert_eq!(
+        report.pages()[0].content_type().as_deref(),
+        Some("image/jpeg")
+    );
+    assert_eq!(
+        report.pages()[1].content_type().as_deref(),
+        Some("image/png")
+    );
+    assert_eq!(*report.pages()[0].retries(), 0);
+    assert_eq!(*report.pages()[2].retries(), 1);
+
+    // Pages from a previous run keep where they came from
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .data_saver(false);
+    let resumed = download(server.client(), req).await.unwrap();
+    assert_eq!(resumed.fetched().count(), 0);
+    assert_eq!(resumed.pages()[1].url(), report.pages()[1].url());
+    assert_eq!(
+        resumed.pages()[1].content_type().as_deref(),
+        Some("image/png")
+    );
+}
+
+#[tokio::test]
+async fn test_incomplete_chapter() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(5);
+    mount_chapter(&server, "chapter", &pages);
+    for i in [1, 3] {
+        server.mount(
+            &format!("/data-saver/{HASH}/{}", pages[i].0),
+            MockResponse::not_found(),
+        );
+    }
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let marker = tmpdir.path().join(INCOMPLETE_MARKER_FILE_NAME);
+    let req = || ChapterDownloadRequest::new("chapter").path(tmpdir.path());
+    let result = download(server.client(), req()).await;
+    let Err(MangadexError::IncompleteChapter(report)) = &result else {
+        panic!("expected an incomplete chapter, got {result:?}");
+    };
+    assert_eq!(
+        result.as_ref().unwrap_err().to_string(),
+        "chapter chapter is incomplete, 2 of 5 pages failed"
+    );
+    let succeeded: Vec<usize> = report.pages().iter().map(|p| *p.index()).collect();
+    let failed: Vec<usize> = report.failed().iter().map(|p| *p.index()).collect();
+    assert_eq!(succeeded, vec![0, 2, 4]);
+    assert_eq!(failed, vec![1, 3]);
+    assert!(!marker.exists());
+
+    let report = download(server.client(), req().allow_incomplete(true))
         .await
-        .expect("Some error");
+        .unwrap();
+    assert!(!report.is_complete());
+    assert_eq!(report.failed().len(), 2);
+    let marker_content = std::fs::read_to_string(&marker).unwrap();
+    assert!(marker_content.contains(&pages[1].0));
+    assert!(marker_content.contains(&pages[3].0));
+    assert!(!ChapterManifest::is_complete(tmpdir.path()));
+
+    mount_chapter(&server, "chapter", &pages);
+    let report = download(server.client(), req()).await.unwrap();
+    assert!(report.is_complete());
+    assert!(!marker.exists());
+    assert!(ChapterManifest::is_complete(tmpdir.path()));
+}
+
+#[tokio::test]
+async fn test_history_entry_of_download() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(4);
+    mount_chapter(&server, "chapter", &pages);
+    server.mount(
+        &format!("/data-saver/{HASH}/{}", pages[2].0),
+        MockResponse::not_found(),
+    );
 
-    assert!(tmpdir.path().join("page_000.jpg").exists());
+    let tmpdir = tempfile::tempdir().unwrap();
+    let req = ChapterDownloadRequest::new("chapter")
+        .path(tmpdir.path())
+        .allow_incomplete(true);
+    let report = download(server.client(), req).await.unwrap();
+    let entry = HistoryEntry::new("manga", "chapter").downloaded(&report);
+
+    let manifest = ChapterManifest::load(tmpdir.path()).unwrap();
+    assert_eq!(entry.pages, 4);
+    assert_eq!(entry.checksums.len(), 4);
+    for page in manifest.pages() {
+        assert_eq!(entry.checksums[*page.index()].as_ref(), Some(page.sha256()));
+    }
+    assert_eq!(entry.checksums[2], None);
+    assert_eq!(entry.missing_pages(), [2]);
+    assert!(!entry.is_complete());
+    assert_eq!(entry.path, tmpdir.path());
+}
+
+#[tokio::test]
+async fn test_stream_into_cbz() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(3);
+    mount_chapter(&server, "good", &pages);
+    let bad_pages: Vec<(&str, &[u8])> = pages
+        .iter()
+        .map(|(name, bytes)| (name.as_str(), bytes.as_slice()))
+        .collect();
+    server.mount_chapter("bad", "badhash", &bad_pages);
+    server.mount(
+        &format!("/data-saver/badhash/{}", pages[1].0),
+        MockResponse::not_found(),
+    );
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let chapters = [
+        PackageChapter::new(tmpdir.path().join("chapter_1")).volume(1),
+        PackageChapter::new(tmpdir.path().join("chapter_2")).volume(1),
+    ];
+    let stream = Packager::new(PackageMode::PerVolume, "Series")
+        .stream(tmpdir.path(), &chapters)
+        .unwrap();
+    for (id, chapter) in ["good", "bad"].into_iter().zip(&chapters) {
+        let req = ChapterDownloadRequest::new(id)
+            .path(chapter.path())
+            .sink(stream.sink(chapter.path()).unwrap());
+        let result = download(server.client(), req).await;
+        assert_eq!(result.is_ok(), id == "good");
+    }
+    assert!(!tmpdir.path().join("chapter_1").exists());
+
+    let written = stream.finish().unwrap();
+    assert_eq!(written, [tmpdir.path().join("Series - Volume 1.cbz")]);
+    let archive = zip::ZipArchive::new(std::fs::File::open(&written[0]).unwrap()).unwrap();
+    let mut names: Vec<&str> = archive.file_names().collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "00000_chapter_1/page_0.jpg",
+            "00000_chapter_1/page_1.png",
+            "00000_chapter_1/page_2.jpg",
+            "ComicInfo.xml"
+        ]
+    );
+    assert!(!tmpdir.path().join("Series - Volume 1.cbz.tmp").exists());
+}
+
+#[tokio::test]
+async fn test_stream_downloaded_and_incomplete_chapters() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(3);
+    mount_chapter(&server, "done", &pages);
+    mount_chapter(&server, "partial", &pages);
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let chapters = [
+        PackageChapter::new(tmpdir.path().join("chapter_1")).volume(1),
+        PackageChapter::new(tmpdir.path().join("chapter_2")).volume(1),
+    ];
+    // The first chapter was downloaded before streaming
+    let req = ChapterDownloadRequest::new("done").path(chapters[0].path());
+    download(server.client(), req).await.unwrap();
+    assert!(ChapterManifest::is_complete(chapters[0].path()));
+    let fetched = server.requests().len();
+
+    server.mount(
+        &format!("/data-saver/{HASH}/{}", pages[1].0),
+        MockResponse::not_found(),
+    );
+
+    let mut stream = Packager::new(PackageMode::PerVolume, "Series")
+        .stream(tmpdir.path(), &chapters)
+        .unwrap();
+    stream.copy_folder(chapters[0].path()).unwrap();
+    let req = ChapterDownloadRequest::new("partial")
+        .path(chapters[1].path())
+        .allow_incomplete(true)
+        .sink(stream.sink(chapters[1].path()).unwrap());
+    let report = download(server.client(), req).await.unwrap();
+    assert!(!report.is_complete());
+    assert!(!chapters[1]
+        .path()
+        .join(INCOMPLETE_MARKER_FILE_NAME)
+        .exists());
+
+    let written = stream.finish().unwrap();
+    assert!(!chapters[0].path().exists());
+    assert!(server.requests()[fetched..]
+        .iter()
+        .all(|r| !r.path().contains("done")));
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&written[0]).unwrap()).unwrap();
+    let mut names: Vec<&str> = archive.file_names().collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "00000_chapter_1/page_0.jpg",
+            "00000_chapter_1/page_1.png",
+            "00000_chapter_1/page_2.jpg",
+            "00001_chapter_2/page_0.jpg",
+            "00001_chapter_2/page_2.jpg",
+            "ComicInfo.xml"
+        ]
+    );
+    let mut xml = String::new();
+    archive
+        .by_name("ComicInfo.xml")
+        .unwrap()
+        .read_to_string(&mut xml)
+        .unwrap();
+    assert!(xml.contains("<Notes>chapter_2 is missing pages:"));
+    assert!(xml.contains("\n  page 1 ("));
+}
+
+#[tokio::test]
+async fn test_package_resumed_chapter() {
+    let server = MockServer::start().await;
+    let pages = sample_pages(3);
+    mount_chapter(&server, "done", &pages);
+    mount_chapter(&server, "partial", &pages);
+
+    let tmpdir = tempfile::tempdir().unwrap();
+    let chapters = [
+        PackageChapter::new(tmpdir.path().join("chapter_1")).volume(1),
+        PackageChapter::new(tmpdir.path().join("chapter_2")).volume(1),
+    ];
+    let req = ChapterDownloadRequest::new("done").path(chapters[0].path());
+    download(server.client(), req).await.unwrap();
+    server.mount(
+        &format!("/data-saver/{HASH}/{}", pages[1].0),
+        MockResponse::not_found(),
+    );
+    let req = ChapterDownloadRequest::new("partial")
+        .path(chapters[1].path())
+        .allow_incomplete(true);
+    assert!(!download(server.client(), req).await.unwrap().is_complete());
+
+    let packager = Packager::new(PackageMode::PerVolume, "Series");
+    let written = packager.package(tmpdir.path(), &chapters).unwrap();
+    assert!(!chapters[0].path().exists());
+    assert!(chapters[1]
+        .path()
+        .join(INCOMPLETE_MARKER_FILE_NAME)
+        .exists());
+    let archive_names = || {
+        let archive = zip::ZipArchive::new(std::fs::File::open(&written[0]).unwrap()).unwrap();
+        let mut names: Vec<String> = archive.file_names().map(String::from).collect();
+        names.sort();
+        names
+    };
+    let notes = || {
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&written[0]).unwrap()).unwrap();
+        let mut xml = String::new();
+        archive
+            .by_name("ComicInfo.xml")
+            .unwrap()
+            .read_to_string(&mut xml)
+            .unwrap();
+        xml
+    };
+    assert_eq!(
+        archive_names(),
+        [
+            "00000_chapter_1/page_0.jpg",
+            "00000_chapter_1/page_1.png",
+            "00000_chapter_1/page_2.jpg",
+            "00001_chapter_2/page_0.jpg",
+            "00001_chapter_2/page_2.jpg",
+            "ComicInfo.xml"
+        ]
+    );
+    assert!(notes().contains("chapter_2 is missing pages:"));
+
+    // Only the missing page is fetched, and the chapter takes its place back
+    mount_chapter(&server, "partial", &pages);
+    let fetched = server.requests().len();
+    let req = ChapterDownloadRequest::new("partial").path(chapters[1].path());
+    assert!(download(server.client(), req).await.unwrap().is_complete());
+    let pages_fetched: Vec<String> = server.requests()[fetched..]
+        .iter()
+        .filter(|r| r.path().starts_with("/data-saver/"))
+        .map(|r| r.path().to_string())
+        .collect();
+    assert_eq!(
+        pages_fetched,
+        [format!("/data-saver/{HASH}/{}", pages[1].0)]
+    );
+    assert!(packager.is_packaged(tmpdir.path(), &chapters[1]));
+
+    packager.package(tmpdir.path(), &chapters[1..]).unwrap();
+    assert!(!chapters[1].path().exists());
+    assert_eq!(
+        archive_names(),
+        [
+            "00000_chapter_1/page_0.jpg",
+            "00000_chapter_1/page_1.png",
+            "00000_chapter_1/page_2.jpg",
+            "00001_chapter_2/page_0.jpg",
+            "00001_chapter_2/page_1.png",
+            "00001_chapter_2/page_2.jpg",
+            "ComicInfo.xml"
+        ]
+    );
+    assert!(!notes().contains("missing pages"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_spawn_and_buffer_downloader() {
+    fn assert_send_static<T: Send + 'static>(_: &T) {}
+
+    let server = MockServer::start().await;
+    let tmpdir = tempfile::tempdir().unwrap();
+    let ids = ["a", "b", "c", "d"];
+    for id in ids {
+        mount_chapter(&server, id, &sample_pages(3));
+    }
+
+    let downloader = ChapterDownloader::new(server.client()).max_connections(4);
+    let buffered = tower::buffer::Buffer::new(downloader.clone(), 8);
+    let mut handles = Vec::new();
+    for (i, id) in ids.into_iter().enumerate() {
+        let req = ChapterDownloadRequest::new(id).path(tmpdir.path().join(id));
+        let handle = if i % 2 == 0 {
+            let mut downloader = downloader.clone();
+            let fut = downloader.call(req);
+            assert_send_static(&fut);
+            tokio::spawn(async move { fut.await.map_err(|e| e.to_string()) })
+        } else {
+            let buffered = buffered.clone();
+            tokio::spawn(async move { buffered.oneshot(req).await.map_err(|e| e.to_string()) })
+        };
+        handles.push(handle);
+    }
+    for handle in handles {
+        assert_eq!(handle.await.unwrap().unwrap().pages().len(), 3);
+    }
+    for id in ids {
+        assert!(ChapterManifest::is_complete(tmpdir.path().join(id)));
+    }
 }